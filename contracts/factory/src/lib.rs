@@ -0,0 +1,30 @@
+//! Minimal stand-in for the staking farm factory, so farms can be upgraded.
+//!
+//! The farm's `upgrade` fetches the new code from its factory with `get_code`, passing the code
+//! hash on as the raw input. This factory only stores code under its sha256 hash and hands it
+//! back; deploying farms is left out.
+
+use near_sdk::env;
+
+/// Store the code given as the raw input and return its sha256 hash.
+#[no_mangle]
+pub extern "C" fn store() {
+    env::setup_panic_hook();
+
+    let code = env::input().expect("ERR_NO_INPUT");
+    let code_hash = env::sha256(&code);
+    env::storage_write(&code_hash, &code);
+
+    env::value_return(&code_hash);
+}
+
+/// Return the code stored under the hash given as the raw input.
+#[no_mangle]
+pub extern "C" fn get_code() {
+    env::setup_panic_hook();
+
+    let code_hash = env::input().expect("ERR_NO_INPUT");
+    let code = env::storage_read(&code_hash).expect("ERR_NO_CODE");
+
+    env::value_return(&code);
+}
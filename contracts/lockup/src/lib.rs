@@ -0,0 +1,50 @@
+//! Minimal stand-in for the lockup contract, so the farm can be tested with delegated stake.
+//!
+//! The farm only needs `get_owner_account_id` to pay out rewards of a lockup to its owner;
+//! everything else the real lockup does is left out.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Gas, PanicOnDefault, Promise};
+
+const DEPOSIT_AND_STAKE_GAS: Gas = Gas(150_000_000_000_000);
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    owner_account_id: AccountId,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new(owner_account_id: AccountId) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+
+        Self { owner_account_id }
+    }
+
+    pub fn get_owner_account_id(&self) -> AccountId {
+        self.owner_account_id.clone()
+    }
+
+    /// Stake `amount` of the lockup's own balance in the given pool.
+    pub fn deposit_and_stake(
+        &mut self,
+        staking_pool_account_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_account_id,
+            "Can only be called by the owner"
+        );
+
+        Promise::new(staking_pool_account_id).function_call(
+            "deposit_and_stake".to_string(),
+            b"{}".to_vec(),
+            amount.0,
+            DEPOSIT_AND_STAKE_GAS,
+        )
+    }
+}
@@ -1,4 +1,8 @@
 #!/bin/bash
 set -e
 
-cargo run --example integration-tests
+./contracts/fungible_token/build.sh
+./contracts/lockup/build.sh
+./contracts/factory/build.sh
+
+cargo run -- "$@"
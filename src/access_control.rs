@@ -0,0 +1,267 @@
+use std::fmt;
+
+use regex::Regex;
+use workspaces::AccountId;
+
+use crate::*;
+
+/// Who calls a privileged method.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Caller {
+    Owner,
+    /// An account the owner added with `add_authorized_user`.
+    AuthorizedUser,
+    /// Any other account.
+    Stranger,
+}
+
+impl Caller {
+    pub const ALL: [Caller; 3] = [Caller::Owner, Caller::AuthorizedUser, Caller::Stranger];
+}
+
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Caller::Owner => "owner",
+            Caller::AuthorizedUser => "authorized_user",
+            Caller::Stranger => "stranger",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    /// The call gets past the permission check. It may still fail for other reasons, e.g.
+    /// `upgrade` without a factory holding the code.
+    Permitted,
+    /// The call fails with one of `PERMISSION_ERRORS`.
+    Denied,
+}
+
+use self::Access::*;
+
+/// Who may call each privileged method of the farm: owner, authorized user, stranger.
+pub const ACCESS_POLICY: &[(&str, [Access; 3])] = &[
+    ("add_authorized_user", [Permitted, Denied, Denied]),
+    ("remove_authorized_user", [Permitted, Denied, Denied]),
+    ("add_authorized_farm_token", [Permitted, Permitted, Denied]),
+    (
+        "remove_authorized_farm_token",
+        [Permitted, Permitted, Denied],
+    ),
+    ("set_owner_id", [Permitted, Denied, Denied]),
+    ("pause_staking", [Permitted, Denied, Denied]),
+    ("resume_staking", [Permitted, Denied, Denied]),
+    ("update_reward_fee_fraction", [Permitted, Denied, Denied]),
+    ("upgrade", [Permitted, Denied, Denied]),
+];
+
+/// Messages the farm rejects unprivileged callers with.
+pub const PERMISSION_ERRORS: &[&str] = &[
+    "Can only be called by the owner",
+    "ERR_NOT_AUTHORIZED_USER",
+    "MUST BE OWNER TO SET OWNER",
+];
+
+/// How a permitted `upgrade` fails in the test env: the farm is its own factory, which has no
+/// `get_code`, so the code can't be fetched.
+pub const UPGRADE_WITHOUT_CODE_ERRORS: &[&str] =
+    &["Failed to fetch the new code", "MethodNotFound"];
+
+/// Everything the privileged methods change, read through the views and, for `paused`, the
+/// farm's storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrivilegedState {
+    pub owner_id: AccountId,
+    pub authorized_users: Vec<AccountId>,
+    pub authorized_farm_tokens: Vec<AccountId>,
+    pub paused: bool,
+    pub reward_fee_fraction: Ratio,
+    pub next_reward_fee_fraction: Ratio,
+}
+
+impl PrivilegedState {
+    pub async fn read(farm: &StakingFarmClient) -> anyhow::Result<Self> {
+        let mut authorized_users = farm.get_authorized_users().await?;
+        let mut authorized_farm_tokens = farm.get_authorized_farm_tokens().await?;
+        authorized_users.sort();
+        authorized_farm_tokens.sort();
+
+        Ok(Self {
+            owner_id: farm.get_owner_id().await?,
+            authorized_users,
+            authorized_farm_tokens,
+            paused: read_farm_state(&farm.contract).await?.paused,
+            reward_fee_fraction: farm.get_reward_fee_fraction().await?,
+            next_reward_fee_fraction: farm.get_pool_summary().await?.next_reward_fee_fraction,
+        })
+    }
+}
+
+/// What `ACCESS_POLICY` says about `caller` calling `method`.
+pub fn expected_access(method: &str, caller: Caller) -> Option<Access> {
+    let (_, access) = ACCESS_POLICY.iter().find(|(name, _)| *name == method)?;
+    let column = Caller::ALL.iter().position(|c| *c == caller).unwrap();
+
+    Some(access[column])
+}
+
+/// One `access:<method>:<caller>` test per cell of `ACCESS_POLICY`.
+pub fn access_control_tests() -> Vec<TestCase> {
+    let mut tests = vec![];
+
+    for (method, _) in ACCESS_POLICY {
+        for caller in Caller::ALL {
+            let name = format!("access:{}:{}", method, caller);
+            tests.push(
+                TestCase::new(&name, move |env| async move {
+                    test_access(&env, method, caller).await
+                })
+                .tags(&["access"]),
+            );
+        }
+    }
+
+    tests
+}
+
+/// Call `method` as `caller` on a farm prepared so the call can succeed, and check that it is
+/// permitted or denied as `ACCESS_POLICY` says. A permitted call has to succeed and make its
+/// change; a denied one has to leave the state as it was.
+pub async fn test_access(env: &TestEnv, method: &str, caller: Caller) -> anyhow::Result<()> {
+    log!("Start: test_access {} as {}", method, caller);
+
+    let expected = expected_access(method, caller)
+        .ok_or_else(|| anyhow::anyhow!("{} is not in the access policy", method))?;
+
+    let owner_farm = StakingFarmClient::new(&env.staking_farm_contract, &env.owner);
+    let authorized_user = env
+        .create_account("authorized", parse_near!("10 N"))
+        .await?;
+    owner_farm.add_authorized_user(authorized_user.id()).await?;
+
+    let signer = match caller {
+        Caller::Owner => env.owner.clone(),
+        Caller::AuthorizedUser => authorized_user,
+        Caller::Stranger => env.alice.clone(),
+    };
+    let farm = owner_farm.with_signer(&signer);
+
+    // accounts the methods are called with, which don't have to exist
+    let target: AccountId = env.account_name("target").parse()?;
+    let token: AccountId = env.account_name("token").parse()?;
+
+    let new_fee = Ratio {
+        numerator: 1,
+        denominator: 10,
+    };
+
+    // prepare the farm so the call can succeed
+    match method {
+        "remove_authorized_user" => {
+            owner_farm.add_authorized_user(&target).await?;
+        }
+        "remove_authorized_farm_token" => {
+            owner_farm.add_authorized_farm_token(&token).await?;
+        }
+        "resume_staking" => {
+            owner_farm.pause_staking().await?;
+        }
+        _ => {}
+    }
+    let before = PrivilegedState::read(&owner_farm).await?;
+
+    // what the state becomes if the call is permitted
+    let mut permitted = before.clone();
+    let call = match method {
+        "add_authorized_user" => {
+            permitted.authorized_users.push(target.clone());
+            farm.add_authorized_user(&target)
+        }
+        "remove_authorized_user" => {
+            permitted.authorized_users.retain(|user| *user != target);
+            farm.remove_authorized_user(&target)
+        }
+        "add_authorized_farm_token" => {
+            permitted.authorized_farm_tokens.push(token.clone());
+            farm.add_authorized_farm_token(&token)
+        }
+        "remove_authorized_farm_token" => {
+            permitted.authorized_farm_tokens.retain(|t| *t != token);
+            farm.remove_authorized_farm_token(&token)
+        }
+        "set_owner_id" => {
+            permitted.owner_id = target.clone();
+            farm.set_owner_id(&target)
+        }
+        "pause_staking" => {
+            permitted.paused = true;
+            farm.pause_staking()
+        }
+        "resume_staking" => {
+            permitted.paused = false;
+            farm.resume_staking()
+        }
+        "update_reward_fee_fraction" => {
+            // takes effect at the next epoch
+            permitted.next_reward_fee_fraction = new_fee.clone();
+            farm.update_reward_fee_fraction(new_fee)
+        }
+        "upgrade" => farm.upgrade(&[0; 32]),
+        _ => anyhow::bail!("no call for {}", method),
+    };
+    permitted.authorized_users.sort();
+    permitted.authorized_farm_tokens.sort();
+
+    let res = call.await;
+    let actual = match &res {
+        Err(err) if any_of(PERMISSION_ERRORS)?.is_matched_by(err) => Denied,
+        _ => Permitted,
+    };
+
+    assert_eq!(
+        actual, expected,
+        "{} called by {}: expected {:?}, got {:?}",
+        method, caller, expected, actual
+    );
+
+    if let Err(err) = res {
+        // a permitted call must succeed, apart from `upgrade` missing the code
+        let expected_error =
+            method == "upgrade" && any_of(UPGRADE_WITHOUT_CODE_ERRORS)?.is_matched_by(&err);
+        if actual == Permitted && !expected_error {
+            anyhow::bail!(
+                "{} called by {} was permitted but failed: {}",
+                method,
+                caller,
+                err
+            );
+        }
+    }
+
+    let after = PrivilegedState::read(&owner_farm).await?;
+    let expected_state = match actual {
+        Permitted => permitted,
+        Denied => before,
+    };
+    assert_eq!(
+        after, expected_state,
+        "{} called by {} ({:?}) left the farm in an unexpected state",
+        method, caller, actual
+    );
+
+    log!("Passed ✅ test_access {} as {}", method, caller);
+    Ok(())
+}
+
+/// A failure matching any of `messages`.
+fn any_of(messages: &[&str]) -> anyhow::Result<ExpectedFailure> {
+    let pattern = messages
+        .iter()
+        .map(|message| regex::escape(message))
+        .collect::<Vec<_>>()
+        .join("|");
+
+    Ok(ExpectedFailure::Matches(Regex::new(&pattern)?))
+}
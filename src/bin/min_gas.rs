@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+
+use integration_tests::fixture::*;
+use integration_tests::min_gas::*;
+
+/// Find the smallest gas each staking farm method succeeds with.
+#[derive(Parser)]
+struct Cli {
+    /// Only probe these methods.
+    methods: Vec<String>,
+    /// Stop the search once the failing and succeeding gas are this close, in TGas.
+    #[arg(long, default_value_t = 1)]
+    precision: u64,
+    /// Also write the results to this CSV file.
+    #[arg(long)]
+    csv: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let fixture = FixtureBuilder::new().build().await?;
+    let precision = cli.precision * 1_000_000_000_000;
+    let mut results = vec![];
+
+    for probe in farm_gas_probes() {
+        if !cli.methods.is_empty() && !cli.methods.iter().any(|m| m == probe.method) {
+            continue;
+        }
+        if let Some(path) = probe.requires.filter(|path| !Path::new(path).exists()) {
+            println!("Skipping {}: {} is not built", probe.method, path);
+            continue;
+        }
+
+        results.push(find_min_gas(&fixture, &probe, precision).await?);
+    }
+
+    print_min_gas_table(&results);
+
+    if let Some(path) = &cli.csv {
+        write_min_gas_csv(&results, path)?;
+        println!("Written to {}", path.display());
+    }
+
+    if results
+        .iter()
+        .any(|result| !result.callback_failures.is_empty())
+    {
+        println!("\nSome methods succeeded while a callback failed, see CALLBACK FAILS AT.");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use integration_tests::fixture::*;
+use integration_tests::property::*;
+
+/// Run random sequences of staking actions against the farm and check them against a model.
+#[derive(Parser)]
+struct Cli {
+    /// Seed of the first sequence; sequence `i` uses `seed + i`.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    #[arg(long, default_value_t = 10)]
+    cases: u64,
+    /// Actions per sequence.
+    #[arg(long, default_value_t = 20)]
+    steps: usize,
+    #[arg(long, default_value_t = 3)]
+    users: usize,
+    /// Sequences shrinking may run to find a smaller failing one.
+    #[arg(long, default_value_t = 50)]
+    max_shrink_runs: usize,
+    /// Where to write the scenario reproducing a failure.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let config = PropertyConfig {
+        seed: cli.seed,
+        cases: cli.cases,
+        steps: cli.steps,
+        users: cli.users,
+        max_shrink_runs: cli.max_shrink_runs,
+        ..Default::default()
+    };
+    let fixture = FixtureBuilder::new().build().await?;
+
+    let failure = match PropertyTest::new(&fixture, config).run().await? {
+        Some(failure) => failure,
+        None => {
+            println!("\nAll {} sequences passed", cli.cases);
+            return Ok(());
+        }
+    };
+
+    let yaml = failure.scenario.to_yaml()?;
+    println!("\nSeed {} failed: {}", failure.seed, failure.error);
+    println!("\nSmallest failing sequence found:\n\n{}", yaml);
+
+    if let Some(path) = &cli.out {
+        std::fs::write(path, &yaml)?;
+        println!(
+            "Written to {}, copy it to scenarios/ to keep it as a test",
+            path.display()
+        );
+    }
+
+    std::process::exit(1);
+}
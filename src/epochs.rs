@@ -0,0 +1,98 @@
+use near_jsonrpc_client::{methods, JsonRpcClient};
+use near_primitives::types::{BlockReference, EpochReference, Finality};
+
+use crate::*;
+
+/// The epoch the sandbox is in, as the contracts see it through `env::epoch_height()`.
+#[derive(Debug, Clone, Copy)]
+pub struct EpochInfo {
+    pub epoch_height: u64,
+    /// Height of the first block of the epoch.
+    pub epoch_start_height: u64,
+    /// Epoch length from the protocol config, in blocks.
+    pub epoch_length: u64,
+}
+
+impl EpochInfo {
+    /// Height at which the next epoch starts.
+    pub fn next_epoch_start_height(&self) -> u64 {
+        self.epoch_start_height + self.epoch_length
+    }
+}
+
+/// Read the current epoch and the epoch length from the sandbox's RPC.
+pub async fn epoch_info(worker: &Worker<Sandbox>) -> anyhow::Result<EpochInfo> {
+    let client = JsonRpcClient::connect(worker.rpc_addr());
+
+    let config = client
+        .call(
+            methods::EXPERIMENTAL_protocol_config::RpcProtocolConfigRequest {
+                block_reference: BlockReference::Finality(Finality::None),
+            },
+        )
+        .await
+        .map_err(|err| anyhow::anyhow!("reading the protocol config failed: {:?}", err))?;
+    let validators = client
+        .call(methods::validators::RpcValidatorRequest {
+            epoch_reference: EpochReference::Latest,
+        })
+        .await
+        .map_err(|err| anyhow::anyhow!("reading the current epoch failed: {:?}", err))?;
+
+    Ok(EpochInfo {
+        epoch_height: validators.epoch_height,
+        epoch_start_height: validators.epoch_start_height,
+        epoch_length: config.epoch_length,
+    })
+}
+
+/// Fast-forward to the first block of the next epoch. Returns the new epoch height.
+pub async fn wait_epoch(worker: &Worker<Sandbox>) -> anyhow::Result<u64> {
+    let start = epoch_info(worker).await?;
+    let height = worker.view_block().await?.height();
+
+    let target_height = start.next_epoch_start_height();
+    if target_height > height {
+        worker.fast_forward(target_height - height).await?;
+    }
+
+    // The new epoch begins with the first block at or past the boundary, which the sandbox
+    // may not have produced yet.
+    let mut epoch = epoch_info(worker).await?;
+    while epoch.epoch_height <= start.epoch_height {
+        worker.fast_forward(1).await?;
+        epoch = epoch_info(worker).await?;
+    }
+
+    log!(
+        "Fast-forwarded {} blocks to epoch {} starting at block {}",
+        epoch.epoch_start_height.saturating_sub(height),
+        epoch.epoch_height,
+        epoch.epoch_start_height
+    );
+
+    Ok(epoch.epoch_height)
+}
+
+/// Fast-forward for a given number of epochs. Returns the new epoch height.
+pub async fn wait_epochs(worker: &Worker<Sandbox>, epochs_num: u64) -> anyhow::Result<u64> {
+    log!("Fast-forwarding {} epochs...", epochs_num);
+
+    let mut epoch_height = epoch_info(worker).await?.epoch_height;
+    for _ in 0..epochs_num {
+        epoch_height = wait_epoch(worker).await?;
+    }
+
+    Ok(epoch_height)
+}
+
+/// Fast-forward until the epoch height is at least `epoch_height`, stopping at the first block
+/// of that epoch. Returns the epoch height reached.
+pub async fn wait_until_epoch(worker: &Worker<Sandbox>, epoch_height: u64) -> anyhow::Result<u64> {
+    let mut current = epoch_info(worker).await?.epoch_height;
+    while current < epoch_height {
+        current = wait_epoch(worker).await?;
+    }
+
+    Ok(current)
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Balance;
+
+use crate::*;
+
+/// A point in time of a farm's schedule.
+#[derive(Debug, Clone, Copy)]
+pub enum FarmTime {
+    /// Block timestamp in nanoseconds.
+    At(u64),
+    /// For `start`, time after the block the farm is created in; for `end`, time after the
+    /// start.
+    After(Duration),
+}
+
+/// The `msg` of the `ft_transfer_call` that creates a farm, as `ft_on_transfer` parses it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmMsg {
+    pub name: String,
+    pub start_date: U64,
+    pub end_date: U64,
+}
+
+impl FarmMsg {
+    /// The message for a farm scheduled from `start` to `end`, created at block timestamp
+    /// `now`.
+    pub fn new(name: &str, start: FarmTime, end: FarmTime, now: u64) -> Self {
+        let (start_date, end_date) = farm_dates(start, end, now);
+
+        Self {
+            name: name.to_string(),
+            start_date: U64(start_date),
+            end_date: U64(end_date),
+        }
+    }
+}
+
+/// Start and end timestamps of a farm scheduled from `start` to `end`, created at block
+/// timestamp `now`.
+pub fn farm_dates(start: FarmTime, end: FarmTime, now: u64) -> (u64, u64) {
+    let start_date = match start {
+        FarmTime::At(timestamp) => timestamp,
+        FarmTime::After(delay) => now + delay.as_nanos() as u64,
+    };
+    let end_date = match end {
+        FarmTime::At(timestamp) => timestamp,
+        FarmTime::After(duration) => start_date + duration.as_nanos() as u64,
+    };
+
+    (start_date, end_date)
+}
+
+/// A farm to create by transferring `amount` of `token` to the staking farm.
+///
+/// Starts 3 seconds after creation and lasts 100 seconds unless told otherwise. Absolute
+/// times allow farms in the past or with `end <= start`, which the farm should reject or
+/// handle.
+#[derive(Clone)]
+pub struct FarmSpec {
+    pub token: FungibleTokenClient,
+    pub amount: Balance,
+    pub name: String,
+    pub start: FarmTime,
+    pub end: FarmTime,
+}
+
+impl FarmSpec {
+    pub fn new(token: &FungibleTokenClient, amount: Balance) -> Self {
+        Self {
+            token: token.clone(),
+            amount,
+            name: "Test".to_string(),
+            start: FarmTime::After(Duration::from_secs(3)),
+            end: FarmTime::After(Duration::from_secs(100)),
+        }
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    pub fn start_at(mut self, timestamp: u64) -> Self {
+        self.start = FarmTime::At(timestamp);
+        self
+    }
+
+    pub fn start_in(mut self, delay: Duration) -> Self {
+        self.start = FarmTime::After(delay);
+        self
+    }
+
+    pub fn end_at(mut self, timestamp: u64) -> Self {
+        self.end = FarmTime::At(timestamp);
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.end = FarmTime::After(duration);
+        self
+    }
+
+    /// The farm's dates if it is created at block timestamp `now`.
+    pub fn dates(&self, now: u64) -> (u64, u64) {
+        farm_dates(self.start, self.end, now)
+    }
+
+    /// The `ft_transfer_call` message if the farm is created at block timestamp `now`.
+    pub fn msg(&self, now: u64) -> FarmMsg {
+        FarmMsg::new(&self.name, self.start, self.end, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msg_serializes_as_ft_on_transfer_expects() {
+        let now = 1_000 * ONE_SEC_IN_NANOSECONDS;
+        let msg = FarmMsg::new(
+            "Test",
+            FarmTime::After(Duration::from_secs(3)),
+            FarmTime::After(Duration::from_secs(100)),
+            now,
+        );
+
+        assert_eq!(
+            serde_json::to_string(&msg).unwrap(),
+            r#"{"name":"Test","start_date":"1003000000000","end_date":"1103000000000"}"#
+        );
+    }
+
+    #[test]
+    fn absolute_dates_are_kept_as_given() {
+        let msg = FarmMsg::new("past", FarmTime::At(10), FarmTime::At(5), 1_000);
+
+        assert_eq!(
+            serde_json::to_string(&msg).unwrap(),
+            r#"{"name":"past","start_date":"10","end_date":"5"}"#
+        );
+        assert_eq!(
+            farm_dates(
+                FarmTime::At(10),
+                FarmTime::After(Duration::from_nanos(0)),
+                1_000
+            ),
+            (10, 10)
+        );
+    }
+}
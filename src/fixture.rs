@@ -0,0 +1,256 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use near_sdk::Balance;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::*;
+
+/// Where the tests of a run get their sandbox from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SandboxMode {
+    /// A fixed pool of sandboxes for the whole run. A test has a sandbox to itself while it
+    /// runs and waits for one to be idle, so moving time only affects that test. Tests are
+    /// isolated from the ones before them by prefixing every account with a unique name.
+    Shared,
+    /// A new sandbox for every test. Slower, but nothing outlives a test.
+    PerTest,
+}
+
+/// Sandbox, accounts and freshly initialized contracts of a single test.
+#[derive(Clone)]
+pub struct TestEnv {
+    pub worker: Worker<Sandbox>,
+    /// Owner of both contracts.
+    pub owner: Account,
+    pub alice: Account,
+    pub validator_contract: Contract,
+    pub staking_farm_contract: Contract,
+    /// Stake the farm counts as its own right after initialization, without holding it in
+    /// the validator. See `InvariantChecker::unowned_stake`.
+    pub farm_unowned_stake: UnownedStake,
+    prefix: String,
+    /// Returns the shared sandbox to the pool once the last clone is dropped.
+    _lease: Option<Arc<WorkerLease>>,
+}
+
+impl TestEnv {
+    /// Name for a new account that doesn't collide with accounts of other tests.
+    pub fn account_name(&self, name: &str) -> String {
+        format!("{}-{}", self.prefix, name)
+    }
+
+    pub async fn create_account(
+        &self,
+        name: &str,
+        near_amount: Balance,
+    ) -> anyhow::Result<Account> {
+        create_account(&self.worker, &self.account_name(name), near_amount).await
+    }
+}
+
+pub struct FixtureBuilder {
+    sandbox: SandboxMode,
+    workers: usize,
+    owner_balance: Balance,
+    user_balance: Balance,
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> Self {
+        Self {
+            sandbox: SandboxMode::Shared,
+            workers: 1,
+            owner_balance: parse_near!("1000 N"),
+            user_balance: parse_near!("2000000 N"),
+        }
+    }
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sandbox(mut self, sandbox: SandboxMode) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Number of sandboxes in the shared pool.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = std::cmp::max(workers, 1);
+        self
+    }
+
+    pub fn owner_balance(mut self, owner_balance: Balance) -> Self {
+        self.owner_balance = owner_balance;
+        self
+    }
+
+    /// Initial balance of `alice`.
+    pub fn user_balance(mut self, user_balance: Balance) -> Self {
+        self.user_balance = user_balance;
+        self
+    }
+
+    /// Start the shared sandboxes, if any.
+    pub async fn build(self) -> anyhow::Result<Fixture> {
+        let pool = if self.sandbox == SandboxMode::Shared {
+            let mut idle = vec![];
+            for _ in 0..self.workers {
+                idle.push(workspaces::sandbox().await?);
+            }
+            Some(Arc::new(WorkerPool {
+                permits: Arc::new(Semaphore::new(idle.len())),
+                idle: Mutex::new(idle),
+            }))
+        } else {
+            None
+        };
+
+        Ok(Fixture {
+            owner_balance: self.owner_balance,
+            user_balance: self.user_balance,
+            pool,
+            next_id: AtomicUsize::new(0),
+        })
+    }
+}
+
+/// Hands out a `TestEnv` per test, with contracts deployed by `deploy_contracts` and
+/// initialized by `init_contracts`.
+pub struct Fixture {
+    owner_balance: Balance,
+    user_balance: Balance,
+    pool: Option<Arc<WorkerPool>>,
+    next_id: AtomicUsize,
+}
+
+/// The shared sandboxes not leased to a test, and a permit for each of them.
+struct WorkerPool {
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<Worker<Sandbox>>>,
+}
+
+impl WorkerPool {
+    /// Wait for an idle sandbox and take it out of the pool.
+    async fn lease(self: &Arc<Self>) -> anyhow::Result<WorkerLease> {
+        let permit = self.permits.clone().acquire_owned().await?;
+        let worker = self
+            .idle
+            .lock()
+            .expect("worker pool lock poisoned")
+            .pop()
+            .expect("a permit guarantees an idle worker");
+
+        Ok(WorkerLease {
+            pool: self.clone(),
+            worker: Some(worker),
+            _permit: permit,
+        })
+    }
+}
+
+/// A shared sandbox leased to one test, put back into the pool on drop.
+struct WorkerLease {
+    pool: Arc<WorkerPool>,
+    worker: Option<Worker<Sandbox>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for WorkerLease {
+    fn drop(&mut self) {
+        // the permit is released after this, once the worker is back
+        if let Some(worker) = self.worker.take() {
+            if let Ok(mut idle) = self.pool.idle.lock() {
+                idle.push(worker);
+            }
+        }
+    }
+}
+
+impl Fixture {
+    pub async fn env(&self, test_name: &str) -> anyhow::Result<TestEnv> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (worker, lease) = match &self.pool {
+            Some(pool) => {
+                let lease = pool.lease().await?;
+                let worker = lease.worker.clone().expect("leased until dropped");
+                (worker, Some(Arc::new(lease)))
+            }
+            None => (workspaces::sandbox().await?, None),
+        };
+        let prefix = format!("t{}-{}", id, account_name_part(test_name));
+
+        // create accounts
+        let owner =
+            create_account(&worker, &format!("{}-owner", prefix), self.owner_balance).await?;
+        let alice =
+            create_account(&worker, &format!("{}-alice", prefix), self.user_balance).await?;
+
+        // deploy contracts
+        let (validator_contract, staking_farm_contract) =
+            deploy_contracts(&worker, &format!("{}-validator", prefix)).await?;
+
+        // initialize contracts
+        init_contracts(&owner, &validator_contract, &staking_farm_contract).await?;
+        let farm_unowned_stake = unowned_stake(&validator_contract, &staking_farm_contract).await?;
+
+        Ok(TestEnv {
+            worker,
+            owner,
+            alice,
+            validator_contract,
+            staking_farm_contract,
+            farm_unowned_stake,
+            prefix,
+            _lease: lease,
+        })
+    }
+}
+
+/// What a freshly initialized farm counts as staked beyond what the validator holds for it,
+/// and the shares of it.
+async fn unowned_stake(
+    validator_contract: &Contract,
+    staking_farm_contract: &Contract,
+) -> anyhow::Result<UnownedStake> {
+    let signer = staking_farm_contract.as_account();
+    let total_staked = StakingFarmClient::new(staking_farm_contract, signer)
+        .get_total_staked_balance()
+        .await?;
+    let validator_staked = ValidatorPoolClient::new(validator_contract, signer)
+        .get_account(staking_farm_contract.id())
+        .await?
+        .staked_balance
+        .0;
+
+    let amount = total_staked.saturating_sub(validator_staked);
+    if amount == 0 {
+        return Ok(UnownedStake::default());
+    }
+
+    let state = read_farm_state(staking_farm_contract).await?;
+    Ok(UnownedStake {
+        amount,
+        shares: mul_div(amount, state.total_stake_shares, state.total_staked_balance),
+    })
+}
+
+/// Lowercase alphanumerics of `name` joined by single dashes and shortened, so that the
+/// prefixed subaccount ids stay valid and well under the length limit.
+fn account_name_part(name: &str) -> String {
+    let part = name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    part.chars()
+        .take(24)
+        .collect::<String>()
+        .trim_end_matches('-')
+        .to_string()
+}
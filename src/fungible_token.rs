@@ -0,0 +1,157 @@
+use near_sdk::{
+    json_types::U128,
+    serde::{de::DeserializeOwned, Deserialize},
+    Balance,
+};
+use workspaces::AccountId;
+
+use crate::*;
+
+#[derive(Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceJson {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// Typed client for the mock NEP-141 token in `contracts/fungible_token`.
+#[derive(Clone)]
+pub struct FungibleTokenClient {
+    pub contract: Contract,
+    pub signer: Account,
+}
+
+impl FungibleTokenClient {
+    pub fn new(contract: &Contract, signer: &Account) -> Self {
+        Self {
+            contract: contract.clone(),
+            signer: signer.clone(),
+        }
+    }
+
+    /// Same contract, different default signer.
+    pub fn with_signer(&self, signer: &Account) -> Self {
+        Self::new(&self.contract, signer)
+    }
+
+    pub fn id(&self) -> &AccountId {
+        self.contract.id()
+    }
+
+    fn call(&self, method: &str) -> ContractCall {
+        ContractCall::new(&self.signer, self.contract.id(), method).contract_name("fungible_token")
+    }
+
+    async fn view<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        args_json: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let res: T = view_call(&self.signer, &self.contract, method, args_json)
+            .await?
+            .json()?;
+
+        Ok(res)
+    }
+
+    pub fn init(&self, name: &str, symbol: &str, decimals: u8) -> ContractCall {
+        self.call("new").args_json(json!({
+            "name": name,
+            "symbol": symbol,
+            "decimals": decimals,
+        }))
+    }
+
+    /// Mint tokens to an account that is already registered with `storage_deposit`.
+    pub fn mint(&self, account_id: &AccountId, amount: Balance) -> ContractCall {
+        self.call("mint").args_json(json!({
+            "account_id": account_id,
+            "amount": U128(amount),
+        }))
+    }
+
+    pub fn storage_deposit(&self, account_id: &AccountId, deposit: Balance) -> ContractCall {
+        self.call("storage_deposit")
+            .args_json(json!({ "account_id": account_id }))
+            .deposit(deposit)
+    }
+
+    /// Pay the minimal storage deposit for every given account.
+    pub async fn register_accounts(&self, account_ids: &[&AccountId]) -> anyhow::Result<()> {
+        let bounds = self.storage_balance_bounds().await?;
+        let min_deposit: Balance = bounds.min.parse()?;
+
+        for account_id in account_ids {
+            self.storage_deposit(account_id, min_deposit).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn ft_transfer(&self, receiver_id: &AccountId, amount: Balance) -> ContractCall {
+        self.call("ft_transfer").deposit(1).args_json(json!({
+            "receiver_id": receiver_id,
+            "amount": U128(amount),
+        }))
+    }
+
+    pub fn ft_transfer_call(
+        &self,
+        receiver_id: &AccountId,
+        amount: Balance,
+        msg: &str,
+    ) -> ContractCall {
+        self.call("ft_transfer_call")
+            .deposit(1)
+            .gas(parse_gas!("200 T") as u64)
+            .args_json(json!({
+                "receiver_id": receiver_id,
+                "amount": U128(amount),
+                "msg": msg,
+            }))
+    }
+
+    pub async fn ft_balance_of(&self, account_id: &AccountId) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view("ft_balance_of", json!({ "account_id": account_id }))
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn ft_total_supply(&self) -> anyhow::Result<u128> {
+        let res: U128 = self.view("ft_total_supply", json!({})).await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn storage_balance_bounds(&self) -> anyhow::Result<StorageBalanceBoundsJson> {
+        self.view("storage_balance_bounds", json!({})).await
+    }
+
+    pub async fn storage_balance_of(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<Option<StorageBalanceJson>> {
+        self.view("storage_balance_of", json!({ "account_id": account_id }))
+            .await
+    }
+}
+
+/// Deploy the mock token to a new subaccount and initialize it, signed by `owner`.
+pub async fn deploy_fungible_token(
+    worker: &Worker<Sandbox>,
+    account_name: &str,
+    owner: &Account,
+) -> anyhow::Result<FungibleTokenClient> {
+    log!("Deploying fungible token...");
+
+    let token_account = create_account(worker, account_name, parse_near!("100 N")).await?;
+    let token_wasm = std::fs::read(FUNGIBLE_TOKEN_WASM_FILEPATH)?;
+    let token_contract = token_account.deploy(&token_wasm).await?.into_result()?;
+
+    let token = FungibleTokenClient::new(&token_contract, owner);
+    token.init("Farm Token", "FARM", 24).await?;
+
+    Ok(token)
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Mutex;
+
+use near_sdk::serde::{Deserialize, Serialize};
+use workspaces::{types::Gas, AccountId};
+
+use crate::*;
+
+/// Gas of every successful `ContractCall` of the run.
+static GAS_SAMPLES: Mutex<Vec<GasSample>> = Mutex::new(Vec::new());
+
+const TGAS: f64 = 1_000_000_000_000.0;
+
+/// Gas used by one successful call.
+#[derive(Debug, Clone)]
+pub struct GasSample {
+    /// `<contract name>::<method>`, e.g. `staking_farm::deposit`.
+    pub method: String,
+    pub attached_gas: Gas,
+    pub total_gas_burnt: Gas,
+    /// Gas burnt by every receipt, in execution order.
+    pub receipts: Vec<ReceiptGas>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReceiptGas {
+    /// `contract` for receipts of the called contract, `signer` for refunds and `other` for
+    /// cross-contract calls, since the account ids themselves differ between runs.
+    pub executor: String,
+    pub gas_burnt: Gas,
+}
+
+/// Remember the gas used by a successful call for the run's `GasReport`.
+pub fn record_gas(
+    method: &str,
+    contract_id: &AccountId,
+    signer_id: &AccountId,
+    attached_gas: Gas,
+    outcome: &CallOutcome,
+) {
+    let receipts = outcome
+        .receipts
+        .iter()
+        .map(|receipt| {
+            let executor = if receipt.executor_id == *contract_id {
+                "contract"
+            } else if receipt.executor_id == *signer_id {
+                "signer"
+            } else {
+                "other"
+            };
+
+            ReceiptGas {
+                executor: executor.to_string(),
+                gas_burnt: receipt.gas_burnt,
+            }
+        })
+        .collect();
+
+    GAS_SAMPLES.lock().unwrap().push(GasSample {
+        method: method.to_string(),
+        attached_gas,
+        total_gas_burnt: outcome.total_gas_burnt,
+        receipts,
+    });
+}
+
+/// Gas used by all calls of one method.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MethodGas {
+    pub calls: usize,
+    /// The most gas any of the calls was given.
+    pub attached_gas: Gas,
+    pub min_gas: Gas,
+    pub max_gas: Gas,
+    pub mean_gas: Gas,
+    /// The most gas burnt by the receipt at each position.
+    pub receipts: Vec<ReceiptGas>,
+}
+
+impl MethodGas {
+    fn from_samples(samples: &[&GasSample]) -> Self {
+        let totals: Vec<Gas> = samples.iter().map(|s| s.total_gas_burnt).collect();
+        let mut receipts: Vec<ReceiptGas> = vec![];
+
+        for sample in samples {
+            for (i, receipt) in sample.receipts.iter().enumerate() {
+                match receipts.get_mut(i) {
+                    Some(max) => max.gas_burnt = std::cmp::max(max.gas_burnt, receipt.gas_burnt),
+                    None => receipts.push(receipt.clone()),
+                }
+            }
+        }
+
+        Self {
+            calls: samples.len(),
+            attached_gas: samples.iter().map(|s| s.attached_gas).max().unwrap_or(0),
+            min_gas: totals.iter().copied().min().unwrap_or(0),
+            max_gas: totals.iter().copied().max().unwrap_or(0),
+            mean_gas: totals.iter().sum::<Gas>() / std::cmp::max(totals.len(), 1) as Gas,
+            receipts,
+        }
+    }
+
+    /// Share of the attached gas burnt by the most expensive call.
+    pub fn attached_gas_used(&self) -> f64 {
+        self.max_gas as f64 / self.attached_gas as f64
+    }
+}
+
+/// Gas usage per method, as written to and read from a report or baseline file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(crate = "near_sdk::serde", transparent)]
+pub struct GasReport {
+    pub methods: BTreeMap<String, MethodGas>,
+}
+
+/// A method that burnt more gas than its baseline allows.
+#[derive(Debug, Clone)]
+pub struct GasRegression {
+    pub method: String,
+    pub baseline: Gas,
+    pub current: Gas,
+}
+
+impl GasReport {
+    /// Report of every call recorded so far.
+    pub fn collect() -> Self {
+        Self::from_samples(&GAS_SAMPLES.lock().unwrap())
+    }
+
+    pub fn from_samples(samples: &[GasSample]) -> Self {
+        let mut by_method: BTreeMap<&str, Vec<&GasSample>> = BTreeMap::new();
+        for sample in samples {
+            by_method.entry(&sample.method).or_default().push(sample);
+        }
+
+        let methods = by_method
+            .into_iter()
+            .map(|(method, samples)| (method.to_string(), MethodGas::from_samples(&samples)))
+            .collect();
+
+        Self { methods }
+    }
+
+    /// Read a report written as JSON by `write`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Take the methods of `other` over, replacing those this report has, e.g. to update a
+    /// baseline from a run of only some of the tests without dropping the other methods.
+    pub fn merge(&mut self, other: &GasReport) {
+        for (method, gas) in &other.methods {
+            self.methods.insert(method.clone(), gas.clone());
+        }
+    }
+
+    /// Write the report as CSV if `path` ends with `.csv`, as JSON otherwise.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let content = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => self.to_csv(),
+            _ => serde_json::to_string_pretty(self)? + "\n",
+        };
+
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// One `total` row per method with its max/min/mean, followed by a row per receipt with
+    /// the receipt's max.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("method,receipt,executor,calls,attached_gas,min_gas,max_gas,mean_gas\n");
+
+        for (method, gas) in &self.methods {
+            writeln!(
+                csv,
+                "{},total,,{},{},{},{},{}",
+                method, gas.calls, gas.attached_gas, gas.min_gas, gas.max_gas, gas.mean_gas
+            )
+            .unwrap();
+
+            for (i, receipt) in gas.receipts.iter().enumerate() {
+                writeln!(
+                    csv,
+                    "{},{},{},,,,{},",
+                    method, i, receipt.executor, receipt.gas_burnt
+                )
+                .unwrap();
+            }
+        }
+
+        csv
+    }
+
+    /// Methods whose max gas exceeds the baseline by more than `tolerance`, e.g. `0.05` for
+    /// 5%. Methods missing from either report are not compared.
+    pub fn regressions(&self, baseline: &GasReport, tolerance: f64) -> Vec<GasRegression> {
+        self.methods
+            .iter()
+            .filter_map(|(method, gas)| {
+                let baseline = baseline.methods.get(method)?;
+                let allowed = baseline.max_gas as f64 * (1.0 + tolerance);
+
+                if gas.max_gas as f64 > allowed {
+                    Some(GasRegression {
+                        method: method.clone(),
+                        baseline: baseline.max_gas,
+                        current: gas.max_gas,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Print max gas per method next to the gas the clients attach.
+    pub fn print_table(&self) {
+        println!(
+            "\n{:<40} {:>6} {:>10} {:>10} {:>10} {:>6}",
+            "METHOD", "CALLS", "MAX TGAS", "MEAN TGAS", "ATTACHED", "USED"
+        );
+
+        for (method, gas) in &self.methods {
+            println!(
+                "{:<40} {:>6} {:>10.2} {:>10.2} {:>10.2} {:>5.0}%",
+                method,
+                gas.calls,
+                gas.max_gas as f64 / TGAS,
+                gas.mean_gas as f64 / TGAS,
+                gas.attached_gas as f64 / TGAS,
+                gas.attached_gas_used() * 100.0
+            );
+        }
+    }
+}
+
+/// Print regressions against the baseline. Returns whether there were none.
+pub fn print_gas_regressions(regressions: &[GasRegression], tolerance: f64) -> bool {
+    for regression in regressions {
+        println!(
+            "GAS REGRESSION {}: {:.2} TGas, baseline {:.2} TGas (+{:.1}%, tolerance {:.1}%)",
+            regression.method,
+            regression.current as f64 / TGAS,
+            regression.baseline as f64 / TGAS,
+            (regression.current as f64 / regression.baseline as f64 - 1.0) * 100.0,
+            tolerance * 100.0
+        );
+    }
+
+    regressions.is_empty()
+}
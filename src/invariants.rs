@@ -0,0 +1,253 @@
+use std::fmt;
+
+use near_sdk::Balance;
+
+use crate::*;
+
+/// Price of a byte of storage, in yoctoNEAR.
+const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
+const ACCOUNTS_PAGE_SIZE: u64 = 100;
+
+/// Checks that the farm's books agree with the validator after every action:
+///
+/// - the staked and unstaked balances of all farm accounts add up to what the validator
+///   reports for the farm account,
+/// - `get_total_staked_balance` equals the validator's staked balance, apart from the stake
+///   the farm owns itself, given with `unowned_stake`. Its shares take part in validator
+///   rewards, so the accounts' staked balance falls short of the validator's by that part,
+/// - the farm's native balance covers its storage and any unstaked balance owed to accounts
+///   that the validator doesn't hold.
+///
+/// Call `check` after each action, or wrap calls with `step`. The first broken invariant is
+/// returned as an `InvariantError` naming the action after which it broke.
+pub struct InvariantChecker {
+    farm: StakingFarmClient,
+    validator: ValidatorPoolClient,
+    tolerance: Balance,
+    unowned_stake: UnownedStake,
+    actions: Vec<String>,
+}
+
+/// Stake the farm counts in `get_total_staked_balance` without holding it in the validator,
+/// and the stake shares it bought, which no account owns.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UnownedStake {
+    pub amount: Balance,
+    pub shares: Balance,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvariantViolation {
+    pub invariant: &'static str,
+    pub expected: Balance,
+    pub actual: Balance,
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, got {}",
+            self.invariant, self.expected, self.actual
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InvariantError {
+    /// The action after which the invariants broke.
+    pub action: String,
+    /// Actions checked before it, all of which kept the invariants.
+    pub previous_actions: Vec<String>,
+    pub violations: Vec<InvariantViolation>,
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "invariants broken after action #{} {:?}",
+            self.previous_actions.len(),
+            self.action
+        )?;
+        for violation in &self.violations {
+            writeln!(f, "  {}", violation)?;
+        }
+        if let Some(last) = self.previous_actions.last() {
+            write!(f, "  last good action: {:?}", last)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Snapshot of the balances the invariants are checked on.
+#[derive(Debug, Clone)]
+struct Books {
+    accounts_staked: Balance,
+    accounts_unstaked: Balance,
+    total_staked: Balance,
+    validator_staked: Balance,
+    validator_unstaked: Balance,
+    /// Current value of the unowned stake shares.
+    unowned_value: Balance,
+    native_balance: Balance,
+    storage_cost: Balance,
+}
+
+impl InvariantChecker {
+    /// Start checking the given farm, assuming all of its stake is held in the validator.
+    pub fn new(staking_farm_contract: &Contract, validator_contract: &Contract) -> Self {
+        Self {
+            farm: StakingFarmClient::new(staking_farm_contract, staking_farm_contract.as_account()),
+            validator: ValidatorPoolClient::new(
+                validator_contract,
+                staking_farm_contract.as_account(),
+            ),
+            tolerance: parse_near!("0.001 N"),
+            unowned_stake: UnownedStake::default(),
+            actions: vec![],
+        }
+    }
+
+    /// Stake the farm owns itself, like `TestEnv::farm_unowned_stake`. None by default.
+    pub fn unowned_stake(mut self, unowned_stake: UnownedStake) -> Self {
+        self.unowned_stake = unowned_stake;
+        self
+    }
+
+    /// How far balances may drift apart from share rounding. 0.001 NEAR by default.
+    pub fn tolerance(mut self, tolerance: Balance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Send a call and check the invariants after it, whether it succeeded or not.
+    pub async fn step(&mut self, label: &str, call: ContractCall) -> anyhow::Result<CallOutcome> {
+        let res = call.await;
+        self.check(label).await?;
+
+        Ok(res?)
+    }
+
+    /// Check the invariants after `action`.
+    pub async fn check(&mut self, action: &str) -> Result<(), InvariantError> {
+        let violations = match self.books().await {
+            Ok(books) => self.violations(&books),
+            Err(err) => {
+                return Err(InvariantError {
+                    action: format!("{} (reading balances failed: {:#})", action, err),
+                    previous_actions: self.actions.clone(),
+                    violations: vec![],
+                })
+            }
+        };
+
+        if !violations.is_empty() {
+            return Err(InvariantError {
+                action: action.to_string(),
+                previous_actions: self.actions.clone(),
+                violations,
+            });
+        }
+
+        log!("Invariants hold after {:?}", action);
+        self.actions.push(action.to_string());
+        Ok(())
+    }
+
+    async fn books(&self) -> anyhow::Result<Books> {
+        let farm_id = self.farm.id();
+
+        let mut accounts_staked = 0;
+        let mut accounts_unstaked = 0;
+        let mut from_index = 0;
+        loop {
+            let page = self
+                .farm
+                .get_accounts(from_index, ACCOUNTS_PAGE_SIZE)
+                .await?;
+            accounts_staked += page.iter().map(|a| a.staked_balance.0).sum::<Balance>();
+            accounts_unstaked += page.iter().map(|a| a.unstaked_balance.0).sum::<Balance>();
+
+            if (page.len() as u64) < ACCOUNTS_PAGE_SIZE {
+                break;
+            }
+            from_index += ACCOUNTS_PAGE_SIZE;
+        }
+
+        let validator_account = self.validator.get_account(farm_id).await?;
+        let farm_details = self.farm.contract.view_account().await?;
+        // the share price has no view, so it's read from storage, only when needed
+        let unowned_value = if self.unowned_stake.shares == 0 {
+            0
+        } else {
+            let state = read_farm_state(&self.farm.contract).await?;
+            mul_div(
+                self.unowned_stake.shares,
+                state.total_staked_balance,
+                state.total_stake_shares,
+            )
+        };
+
+        Ok(Books {
+            accounts_staked,
+            accounts_unstaked,
+            total_staked: self.farm.get_total_staked_balance().await?,
+            validator_staked: validator_account.staked_balance.0,
+            validator_unstaked: validator_account.unstaked_balance.0,
+            unowned_value,
+            native_balance: farm_details.balance,
+            storage_cost: farm_details.storage_usage as Balance * STORAGE_PRICE_PER_BYTE,
+        })
+    }
+
+    fn violations(&self, books: &Books) -> Vec<InvariantViolation> {
+        let mut violations = vec![];
+        let mut check_eq = |invariant, expected: Balance, actual: Balance| {
+            let diff = if expected > actual {
+                expected - actual
+            } else {
+                actual - expected
+            };
+            if diff > self.tolerance {
+                violations.push(InvariantViolation {
+                    invariant,
+                    expected,
+                    actual,
+                });
+            }
+        };
+
+        check_eq(
+            "sum of accounts' staked balance == validator staked balance of the farm - rewards of unowned shares",
+            (books.validator_staked + self.unowned_stake.amount).saturating_sub(books.unowned_value),
+            books.accounts_staked,
+        );
+        check_eq(
+            "sum of accounts' unstaked balance == validator unstaked balance of the farm",
+            books.validator_unstaked,
+            books.accounts_unstaked,
+        );
+        check_eq(
+            "get_total_staked_balance == validator staked balance of the farm",
+            books.validator_staked + self.unowned_stake.amount,
+            books.total_staked,
+        );
+
+        let obligations = books.storage_cost
+            + books
+                .accounts_unstaked
+                .saturating_sub(books.validator_unstaked);
+        if books.native_balance + self.tolerance < obligations {
+            violations.push(InvariantViolation {
+                invariant: "farm native balance >= storage cost + unstaked balance not held by the validator",
+                expected: obligations,
+                actual: books.native_balance,
+            });
+        }
+
+        violations
+    }
+}
@@ -0,0 +1,78 @@
+use near_sdk::{json_types::U128, Balance};
+use workspaces::AccountId;
+
+use crate::*;
+
+/// Client for the mock lockup in `contracts/lockup`, used to stake on behalf of a delegator.
+#[derive(Clone)]
+pub struct LockupClient {
+    pub contract: Contract,
+    pub signer: Account,
+}
+
+impl LockupClient {
+    pub fn new(contract: &Contract, signer: &Account) -> Self {
+        Self {
+            contract: contract.clone(),
+            signer: signer.clone(),
+        }
+    }
+
+    pub fn id(&self) -> &AccountId {
+        self.contract.id()
+    }
+
+    fn call(&self, method: &str) -> ContractCall {
+        ContractCall::new(&self.signer, self.contract.id(), method).contract_name("lockup")
+    }
+
+    pub fn init(&self, owner_account_id: &AccountId) -> ContractCall {
+        self.call("new")
+            .args_json(json!({ "owner_account_id": owner_account_id }))
+    }
+
+    /// Stake `amount` of the lockup's own balance in the given pool. Must be signed by the
+    /// lockup owner.
+    pub fn deposit_and_stake(
+        &self,
+        staking_pool_account_id: &AccountId,
+        amount: Balance,
+    ) -> ContractCall {
+        self.call("deposit_and_stake")
+            .args_json(json!({
+                "staking_pool_account_id": staking_pool_account_id,
+                "amount": U128(amount),
+            }))
+            .gas(parse_gas!("300 T") as u64)
+    }
+
+    pub async fn get_owner_account_id(&self) -> anyhow::Result<AccountId> {
+        let res: AccountId = view_call(
+            &self.signer,
+            &self.contract,
+            "get_owner_account_id",
+            json!({}),
+        )
+        .await?
+        .json()?;
+
+        Ok(res)
+    }
+}
+
+/// Deploy a mock lockup holding `balance` to a new subaccount and make `owner` its owner.
+pub async fn deploy_lockup(
+    worker: &Worker<Sandbox>,
+    account_name: &str,
+    owner: &Account,
+    balance: Balance,
+) -> anyhow::Result<LockupClient> {
+    let lockup_account = create_account(worker, account_name, balance).await?;
+    let lockup_wasm = std::fs::read(LOCKUP_WASM_FILEPATH)?;
+    let lockup_contract = lockup_account.deploy(&lockup_wasm).await?.into_result()?;
+
+    let lockup = LockupClient::new(&lockup_contract, owner);
+    lockup.init(owner.id()).await?;
+
+    Ok(lockup)
+}
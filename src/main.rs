@@ -0,0 +1,126 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use clap::Parser;
+
+use integration_tests::fixture::*;
+use integration_tests::gas::*;
+use integration_tests::registry::*;
+use integration_tests::suite::all_tests;
+
+/// Run the staking farm integration tests against a local sandbox.
+#[derive(Parser)]
+struct Cli {
+    /// Only run tests whose name matches one of these patterns; `*` is a wildcard.
+    patterns: Vec<String>,
+    /// Only run tests with a tag matching this pattern. Can be repeated.
+    #[arg(long = "tag", short = 't')]
+    tags: Vec<String>,
+    /// Print the selected tests instead of running them.
+    #[arg(long)]
+    list: bool,
+    /// Stop at the first failed test.
+    #[arg(long)]
+    fail_fast: bool,
+    /// Number of tests to run at once. With more than one, the output of each test is printed
+    /// when it finishes.
+    #[arg(long, short = 'j', default_value_t = 1)]
+    jobs: usize,
+    /// Number of shared sandboxes the tests are spread over. Defaults to `--jobs`.
+    #[arg(long)]
+    workers: Option<usize>,
+    /// Start a new sandbox for every test instead of sharing a pool for the whole run.
+    #[arg(long)]
+    sandbox_per_test: bool,
+    /// Write the gas used per method to this file, as CSV if it ends with `.csv` and as JSON
+    /// otherwise.
+    #[arg(long)]
+    gas_report: Option<PathBuf>,
+    /// Fail the run if a method burns more gas than in this JSON report.
+    #[arg(long, default_value = "gas_baseline.json")]
+    gas_baseline: PathBuf,
+    /// Allowed growth over the baseline, e.g. 0.05 for 5%.
+    #[arg(long, default_value_t = 0.05)]
+    gas_tolerance: f64,
+    /// Update the baseline with the gas used by this run instead of comparing. Methods the run
+    /// didn't call keep their baseline.
+    #[arg(long)]
+    update_gas_baseline: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let tests = all_tests()?;
+    let selected = select_tests(&tests, &cli.patterns, &cli.tags);
+
+    if cli.list {
+        for test in &selected {
+            println!("{} [{}]", test.name, test.tags.join(", "));
+        }
+        return Ok(());
+    }
+
+    let sandbox = if cli.sandbox_per_test {
+        SandboxMode::PerTest
+    } else {
+        SandboxMode::Shared
+    };
+    let fixture = FixtureBuilder::new()
+        .sandbox(sandbox)
+        .workers(cli.workers.unwrap_or(cli.jobs))
+        .build()
+        .await?;
+    let reports = run_tests(Arc::new(fixture), &selected, cli.jobs, cli.fail_fast).await;
+
+    let passed = print_summary(&reports);
+    let gas_ok = check_gas(&cli)?;
+
+    if !passed || !gas_ok {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Write the gas report and compare it with the baseline. Returns whether there were no
+/// regressions.
+fn check_gas(cli: &Cli) -> anyhow::Result<bool> {
+    let report = GasReport::collect();
+    report.print_table();
+
+    if let Some(path) = &cli.gas_report {
+        report.write(path)?;
+        println!("Gas report written to {}", path.display());
+    }
+
+    if cli.update_gas_baseline {
+        let mut baseline = if cli.gas_baseline.exists() {
+            GasReport::load(&cli.gas_baseline)?
+        } else {
+            GasReport::default()
+        };
+        baseline.merge(&report);
+        baseline.write(&cli.gas_baseline)?;
+        println!(
+            "Gas baseline {} updated with {} methods",
+            cli.gas_baseline.display(),
+            report.methods.len()
+        );
+        return Ok(true);
+    }
+
+    if !cli.gas_baseline.exists() {
+        println!(
+            "Warning: no gas baseline at {}, skipping the gas comparison. Run all tests with --update-gas-baseline to create it",
+            cli.gas_baseline.display()
+        );
+        return Ok(true);
+    }
+
+    let baseline = GasReport::load(&cli.gas_baseline)?;
+    let regressions = report.regressions(&baseline, cli.gas_tolerance);
+
+    Ok(print_gas_regressions(&regressions, cli.gas_tolerance))
+}
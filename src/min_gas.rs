@@ -0,0 +1,415 @@
+use std::fmt::Write as _;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use near_sdk::Balance;
+use workspaces::{types::Gas, AccountId};
+
+use crate::*;
+
+const TGAS: Gas = 1_000_000_000_000;
+/// The most gas a single transaction can be given.
+pub const MAX_PREPAID_GAS: Gas = 300 * TGAS;
+
+type PrepareFuture = Pin<Box<dyn Future<Output = anyhow::Result<ContractCall>> + Send>>;
+type PrepareFn = Box<dyn Fn(TestEnv) -> PrepareFuture + Send + Sync>;
+
+/// A farm method whose minimum gas is searched for.
+///
+/// `prepare` brings a fresh `TestEnv` into a state where the method can succeed and returns
+/// the call with its default gas; the search only changes the gas.
+pub struct GasProbe {
+    pub method: &'static str,
+    pub requires: Option<&'static str>,
+    prepare: PrepareFn,
+}
+
+impl GasProbe {
+    pub fn new<F, Fut>(method: &'static str, prepare: F) -> Self
+    where
+        F: Fn(TestEnv) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<ContractCall>> + Send + 'static,
+    {
+        Self {
+            method,
+            requires: None,
+            prepare: Box::new(move |env| Box::pin(prepare(env))),
+        }
+    }
+
+    /// Skip the probe if this wasm file is not built.
+    pub fn requires(mut self, wasm_filepath: &'static str) -> Self {
+        self.requires = Some(wasm_filepath);
+        self
+    }
+}
+
+/// Result of a call with a given amount of gas.
+#[derive(Debug, Clone)]
+pub enum ProbeResult {
+    Success {
+        gas_burnt: Gas,
+    },
+    /// The method itself failed, e.g. ran out of gas before scheduling its promises.
+    Failed(String),
+    /// The method succeeded, but a cross-contract call or callback it scheduled failed. The
+    /// farm may be left with state that is out of sync with the validator.
+    CallbackFailed(String),
+}
+
+impl ProbeResult {
+    fn from_call(res: Result<CallOutcome, CallError>) -> Self {
+        match res {
+            Ok(outcome) => ProbeResult::Success {
+                gas_burnt: outcome.total_gas_burnt,
+            },
+            Err(err) => {
+                let method_succeeded = err
+                    .outcome
+                    .receipts
+                    .first()
+                    .map_or(false, |receipt| !receipt.is_failure());
+                let failed = err
+                    .failed_receipts()
+                    .map(|receipt| {
+                        let message = receipt
+                            .panic_message
+                            .clone()
+                            .or_else(|| receipt.failure.clone())
+                            .unwrap_or_default();
+                        format!("{}: {}", receipt.executor_id, message)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+
+                if method_succeeded {
+                    ProbeResult::CallbackFailed(failed)
+                } else {
+                    ProbeResult::Failed(err.to_string())
+                }
+            }
+        }
+    }
+}
+
+/// Outcome of the search for one method.
+#[derive(Debug, Clone)]
+pub struct MinGas {
+    pub method: &'static str,
+    /// Gas the client attaches by default.
+    pub default_gas: Gas,
+    /// Smallest gas, within the search precision, at which the call fully succeeds. `None`
+    /// if it doesn't succeed even with `MAX_PREPAID_GAS`.
+    pub min_gas: Option<Gas>,
+    /// Gas burnt by the call given `min_gas`.
+    pub gas_burnt: Gas,
+    /// Every probed amount of gas at which the method succeeded but a callback failed.
+    pub callback_failures: Vec<(Gas, String)>,
+}
+
+impl MinGas {
+    /// `min_gas` with a quarter on top, rounded up to 5 TGas.
+    pub fn suggested_gas(&self) -> Option<Gas> {
+        let step = 5 * TGAS;
+        self.min_gas.map(|min_gas| {
+            std::cmp::min((min_gas * 5 / 4 + step - 1) / step * step, MAX_PREPAID_GAS)
+        })
+    }
+
+    pub fn default_is_safe(&self) -> bool {
+        self.suggested_gas()
+            .map_or(false, |suggested| self.default_gas >= suggested)
+    }
+}
+
+/// Run the probe with `gas` instead of the default gas. Returns the default gas as well.
+async fn probe(
+    fixture: &Fixture,
+    probe: &GasProbe,
+    gas: Gas,
+) -> anyhow::Result<(Gas, ProbeResult)> {
+    let env = fixture.env(probe.method).await?;
+    let call = (probe.prepare)(env).await?;
+    let default_gas = call.attached_gas();
+
+    Ok((default_gas, ProbeResult::from_call(call.gas(gas).await)))
+}
+
+/// Binary search the smallest gas at which `probe` succeeds, down to `precision`. Every
+/// attempt runs in a fresh `TestEnv`, so earlier attempts don't change the outcome.
+pub async fn find_min_gas(
+    fixture: &Fixture,
+    gas_probe: &GasProbe,
+    precision: Gas,
+) -> anyhow::Result<MinGas> {
+    let mut callback_failures = vec![];
+    let mut record = |gas: Gas, probe_result: &ProbeResult| {
+        log!(
+            "{} with {} TGas: {:?}",
+            gas_probe.method,
+            gas / TGAS,
+            probe_result
+        );
+        if let ProbeResult::CallbackFailed(message) = probe_result {
+            callback_failures.push((gas, message.clone()));
+        }
+    };
+
+    let (default_gas, max_result) = probe(fixture, gas_probe, MAX_PREPAID_GAS).await?;
+    record(MAX_PREPAID_GAS, &max_result);
+
+    let mut min_gas = None;
+    let mut gas_burnt = 0;
+
+    if let ProbeResult::Success { gas_burnt: burnt } = max_result {
+        gas_burnt = burnt;
+
+        // `low` always fails, `high` always succeeds
+        let (mut low, mut high) = (0, MAX_PREPAID_GAS);
+        while high - low > precision {
+            let mid = low + (high - low) / 2;
+            let (_, mid_result) = probe(fixture, gas_probe, mid).await?;
+            record(mid, &mid_result);
+
+            match mid_result {
+                ProbeResult::Success { gas_burnt: burnt } => {
+                    high = mid;
+                    gas_burnt = burnt;
+                }
+                _ => low = mid,
+            }
+        }
+
+        min_gas = Some(high);
+    }
+
+    callback_failures.sort_by_key(|(gas, _)| *gas);
+
+    Ok(MinGas {
+        method: gas_probe.method,
+        default_gas,
+        min_gas,
+        gas_burnt,
+        callback_failures,
+    })
+}
+
+/// Every change method of the farm a user, the owner or a token calls, each prepared so it
+/// succeeds given enough gas.
+///
+/// Left out are `upgrade`, which always gets the most gas a transaction can have since it
+/// deploys code and calls `migrate`, and the private callbacks, whose gas is set by the methods
+/// scheduling them and probed through those.
+pub fn farm_gas_probes() -> Vec<GasProbe> {
+    let amount = parse_near!("100 N");
+
+    vec![
+        GasProbe::new("deposit", move |env| async move {
+            Ok(user_farm(&env).deposit(amount))
+        }),
+        GasProbe::new("deposit_and_stake", move |env| async move {
+            Ok(user_farm(&env).deposit_and_stake(amount))
+        }),
+        GasProbe::new("stake", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit(amount).await?;
+            Ok(farm.stake(amount / 2))
+        }),
+        GasProbe::new("stake_all", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit(amount).await?;
+            Ok(farm.stake_all())
+        }),
+        GasProbe::new("unstake", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit_and_stake(amount).await?;
+            Ok(farm.unstake(amount / 2))
+        }),
+        GasProbe::new("unstake_all", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit_and_stake(amount).await?;
+            Ok(farm.unstake_all())
+        }),
+        GasProbe::new("withdraw", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit(amount).await?;
+            Ok(farm.withdraw(amount / 2))
+        }),
+        GasProbe::new("withdraw_all", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit(amount).await?;
+            Ok(farm.withdraw_all())
+        }),
+        GasProbe::new("ping", move |env| async move {
+            let farm = user_farm(&env);
+            farm.deposit_and_stake(amount).await?;
+            Ok(farm.ping())
+        }),
+        GasProbe::new("pause_staking", |env| async move {
+            Ok(owner_farm(&env).pause_staking())
+        }),
+        GasProbe::new("resume_staking", |env| async move {
+            let farm = owner_farm(&env);
+            farm.pause_staking().await?;
+            Ok(farm.resume_staking())
+        }),
+        GasProbe::new("update_reward_fee_fraction", |env| async move {
+            Ok(owner_farm(&env).update_reward_fee_fraction(Ratio {
+                numerator: 1,
+                denominator: 10,
+            }))
+        }),
+        GasProbe::new("set_owner_id", |env| async move {
+            let new_owner: AccountId = env.account_name("new-owner").parse()?;
+            Ok(owner_farm(&env).set_owner_id(&new_owner))
+        }),
+        GasProbe::new("add_authorized_user", |env| async move {
+            Ok(owner_farm(&env).add_authorized_user(env.alice.id()))
+        }),
+        GasProbe::new("remove_authorized_user", |env| async move {
+            let farm = owner_farm(&env);
+            farm.add_authorized_user(env.alice.id()).await?;
+            Ok(farm.remove_authorized_user(env.alice.id()))
+        }),
+        GasProbe::new("add_authorized_farm_token", |env| async move {
+            let token: AccountId = env.account_name("token").parse()?;
+            Ok(owner_farm(&env).add_authorized_farm_token(&token))
+        }),
+        GasProbe::new("remove_authorized_farm_token", |env| async move {
+            let token: AccountId = env.account_name("token").parse()?;
+            let farm = owner_farm(&env);
+            farm.add_authorized_farm_token(&token).await?;
+            Ok(farm.remove_authorized_farm_token(&token))
+        }),
+        // farm creation: the token's `ft_transfer_call`, the farm's `ft_on_transfer` and the
+        // token's `ft_resolve_transfer` callback
+        GasProbe::new("create_farm", move |env| async move {
+            let token = farm_token(&env, amount).await?;
+            let now = env.worker.view_block().await?.timestamp();
+            let msg = serde_json::to_string(&FarmSpec::new(&token, amount).msg(now))?;
+
+            Ok(token.ft_transfer_call(env.staking_farm_contract.id(), amount, &msg))
+        })
+        .requires(FUNGIBLE_TOKEN_WASM_FILEPATH),
+        GasProbe::new("stop_farm", move |env| async move {
+            let token = farm_token(&env, amount).await?;
+            let farm = owner_farm(&env);
+            let farm_id = farm
+                .create_farm_and_get_id(&env.worker, &FarmSpec::new(&token, amount))
+                .await?;
+
+            Ok(farm.stop_farm(farm_id))
+        })
+        .requires(FUNGIBLE_TOKEN_WASM_FILEPATH),
+        GasProbe::new("claim", move |env| async move {
+            let farm = user_farm(&env);
+            let token = farm_token(&env, amount).await?;
+
+            farm.deposit_and_stake(amount).await?;
+            owner_farm(&env)
+                .transfer_farm_token(&env.worker, &token, amount)
+                .await?;
+            env.worker.fast_forward(10).await?;
+
+            Ok(farm.claim(token.id(), None))
+        })
+        .requires(FUNGIBLE_TOKEN_WASM_FILEPATH),
+    ]
+}
+
+fn user_farm(env: &TestEnv) -> StakingFarmClient {
+    StakingFarmClient::new(&env.staking_farm_contract, &env.alice)
+}
+
+fn owner_farm(env: &TestEnv) -> StakingFarmClient {
+    StakingFarmClient::new(&env.staking_farm_contract, &env.owner)
+}
+
+/// A token authorized for farms, with `amount` minted to the owner, who signs its calls.
+async fn farm_token(env: &TestEnv, amount: Balance) -> anyhow::Result<FungibleTokenClient> {
+    let token = deploy_fungible_token(&env.worker, &env.account_name("token"), &env.owner).await?;
+    token
+        .register_accounts(&[
+            env.owner.id(),
+            env.staking_farm_contract.id(),
+            env.alice.id(),
+        ])
+        .await?;
+    token.mint(env.owner.id(), amount).await?;
+    owner_farm(env)
+        .add_authorized_farm_token(token.id())
+        .await?;
+
+    Ok(token)
+}
+
+/// Print the search results, flagging methods whose default gas is below the suggestion.
+pub fn print_min_gas_table(results: &[MinGas]) {
+    println!(
+        "\n{:<28} {:>8} {:>8} {:>8} {:>10} {:>5}  CALLBACK FAILS AT",
+        "METHOD", "DEFAULT", "MIN", "BURNT", "SUGGESTED", "SAFE"
+    );
+
+    for result in results {
+        let tgas = |gas: Option<Gas>| match gas {
+            Some(gas) => format!("{:.1}", gas as f64 / TGAS as f64),
+            None => "-".to_string(),
+        };
+        let callback_failures = result
+            .callback_failures
+            .iter()
+            .map(|(gas, _)| format!("{:.1}", *gas as f64 / TGAS as f64))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!(
+            "{:<28} {:>8} {:>8} {:>8} {:>10} {:>5}  {}",
+            result.method,
+            tgas(Some(result.default_gas)),
+            tgas(result.min_gas),
+            tgas(Some(result.gas_burnt).filter(|_| result.min_gas.is_some())),
+            tgas(result.suggested_gas()),
+            if result.default_is_safe() {
+                "yes"
+            } else {
+                "NO"
+            },
+            callback_failures
+        );
+    }
+
+    println!("\nAll amounts in TGas. SUGGESTED is MIN + 25% rounded up to 5 TGas.");
+}
+
+/// Write the search results as CSV.
+pub fn write_min_gas_csv(results: &[MinGas], path: &Path) -> anyhow::Result<()> {
+    let mut csv =
+        String::from("method,default_gas,min_gas,gas_burnt,suggested_gas,callback_fails_at\n");
+
+    for result in results {
+        let gas = |gas: Option<Gas>| gas.map(|gas| gas.to_string()).unwrap_or_default();
+
+        let callback_failures = result
+            .callback_failures
+            .iter()
+            .map(|(gas, _)| gas.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        writeln!(
+            csv,
+            "{},{},{},{},{},{}",
+            result.method,
+            result.default_gas,
+            gas(result.min_gas),
+            gas(Some(result.gas_burnt).filter(|_| result.min_gas.is_some())),
+            gas(result.suggested_gas()),
+            callback_failures
+        )
+        .unwrap();
+    }
+
+    std::fs::write(path, csv)?;
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::future::IntoFuture;
+
+use near_sdk::serde::de::DeserializeOwned;
+use regex::Regex;
+use workspaces::{result::ExecutionOutcome, types::Gas, AccountId};
+
+use crate::*;
+
+const PANIC_PREFIX: &str = "Smart contract panicked: ";
+
+/// Outcome of a single receipt produced by a call.
+#[derive(Debug, Clone)]
+pub struct ReceiptOutcome {
+    pub executor_id: AccountId,
+    pub logs: Vec<String>,
+    pub gas_burnt: Gas,
+    /// Debug representation of the receipt error, if the receipt failed.
+    pub failure: Option<String>,
+    /// Message the contract panicked with, if the receipt failed with a panic.
+    pub panic_message: Option<String>,
+}
+
+impl ReceiptOutcome {
+    fn from_outcome(outcome: &ExecutionOutcome) -> Self {
+        let failure = if outcome.is_failure() {
+            outcome
+                .clone()
+                .into_result()
+                .err()
+                .map(|err| format!("{:?}", err))
+        } else {
+            None
+        };
+
+        Self {
+            executor_id: outcome.executor_id.clone(),
+            logs: outcome.logs.clone(),
+            gas_burnt: outcome.gas_burnt,
+            panic_message: failure.as_deref().and_then(extract_panic_message),
+            failure,
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.failure.is_some()
+    }
+}
+
+/// Successful result of a contract method call.
+#[derive(Debug, Clone, Default)]
+pub struct CallOutcome {
+    /// Human readable name of the call, e.g. `farm.test.near::deposit`.
+    pub label: String,
+    /// Logs of the transaction and all of its receipts.
+    pub logs: Vec<String>,
+    pub total_gas_burnt: Gas,
+    pub receipts: Vec<ReceiptOutcome>,
+    /// Raw return value of the call.
+    pub value: Vec<u8>,
+}
+
+impl CallOutcome {
+    /// Build an outcome from the result of a transaction, failing if the transaction or any of
+    /// its receipts failed.
+    pub fn from_final_result(label: &str, res: ExecutionFinalResult) -> Result<Self, CallError> {
+        let mut outcome = CallOutcome {
+            label: label.to_string(),
+            logs: res.logs().into_iter().map(String::from).collect(),
+            total_gas_burnt: res.total_gas_burnt,
+            receipts: res
+                .receipt_outcomes()
+                .iter()
+                .map(ReceiptOutcome::from_outcome)
+                .collect(),
+            value: vec![],
+        };
+        let has_receipt_failures = outcome.receipts.iter().any(ReceiptOutcome::is_failure);
+
+        match res.into_result() {
+            Ok(success) if !has_receipt_failures => {
+                outcome.value = success.raw_bytes().unwrap_or_default();
+                Ok(outcome)
+            }
+            Ok(_) => Err(CallError {
+                outcome,
+                failure: None,
+            }),
+            Err(failure) => Err(CallError {
+                outcome,
+                failure: Some(format!("{:?}", failure)),
+            }),
+        }
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_slice(&self.value)?)
+    }
+}
+
+/// Failed contract method call.
+///
+/// Carries everything that was observed before the failure, so negative tests can inspect
+/// which receipt failed and why.
+#[derive(Debug, Clone)]
+pub struct CallError {
+    /// Whatever was executed; empty when the transaction never reached the chain.
+    pub outcome: CallOutcome,
+    /// Top-level failure of the transaction, or the RPC error if it could not be sent.
+    pub failure: Option<String>,
+}
+
+impl CallError {
+    /// Error for a call that could not be sent or whose result could not be fetched.
+    pub fn rpc(label: &str, err: impl fmt::Display) -> Self {
+        Self {
+            outcome: CallOutcome {
+                label: label.to_string(),
+                ..Default::default()
+            },
+            failure: Some(err.to_string()),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.outcome.label
+    }
+
+    pub fn failed_receipts(&self) -> impl Iterator<Item = &ReceiptOutcome> {
+        self.outcome.receipts.iter().filter(|r| r.is_failure())
+    }
+
+    /// Message of the first contract panic, looking at the top-level failure first.
+    pub fn panic_message(&self) -> Option<String> {
+        self.failure
+            .as_deref()
+            .and_then(extract_panic_message)
+            .or_else(|| self.failed_receipts().find_map(|r| r.panic_message.clone()))
+    }
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed", self.label())?;
+
+        if let Some(message) = self.panic_message() {
+            write!(f, ": {}", message)?;
+        } else if let Some(failure) = &self.failure {
+            write!(f, ": {}", failure)?;
+        }
+
+        for receipt in self.failed_receipts() {
+            write!(
+                f,
+                "\n  receipt on {} ({} gas burnt): {}",
+                receipt.executor_id,
+                receipt.gas_burnt,
+                receipt.failure.as_deref().unwrap_or_default()
+            )?;
+            for log in &receipt.logs {
+                write!(f, "\n    log: {}", log)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// What a call expected to fail is supposed to fail with.
+pub enum ExpectedFailure {
+    /// Any failure is fine.
+    Any,
+    /// The failure text contains the given substring.
+    Contains(String),
+    /// The failure text matches the given regex.
+    Matches(Regex),
+}
+
+impl ExpectedFailure {
+    pub fn is_matched_by(&self, err: &CallError) -> bool {
+        let texts = err
+            .panic_message()
+            .into_iter()
+            .chain(err.failure.clone())
+            .chain(err.failed_receipts().filter_map(|r| r.failure.clone()));
+
+        match self {
+            ExpectedFailure::Any => true,
+            ExpectedFailure::Contains(needle) => texts.into_iter().any(|t| t.contains(needle)),
+            ExpectedFailure::Matches(re) => texts.into_iter().any(|t| re.is_match(&t)),
+        }
+    }
+}
+
+impl fmt::Display for ExpectedFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedFailure::Any => write!(f, "any failure"),
+            ExpectedFailure::Contains(needle) => write!(f, "failure containing {:?}", needle),
+            ExpectedFailure::Matches(re) => write!(f, "failure matching /{}/", re),
+        }
+    }
+}
+
+impl From<&str> for ExpectedFailure {
+    fn from(needle: &str) -> Self {
+        ExpectedFailure::Contains(needle.to_string())
+    }
+}
+
+impl From<Regex> for ExpectedFailure {
+    fn from(re: Regex) -> Self {
+        ExpectedFailure::Matches(re)
+    }
+}
+
+/// Run a call and assert it fails with anything.
+pub async fn assert_call_fails<C>(call: C) -> CallError
+where
+    C: IntoFuture<Output = Result<CallOutcome, CallError>>,
+{
+    assert_call_fails_with(call, ExpectedFailure::Any).await
+}
+
+/// Run a call and assert it fails with the expected message, returning the error for further
+/// inspection.
+pub async fn assert_call_fails_with<C>(call: C, expected: impl Into<ExpectedFailure>) -> CallError
+where
+    C: IntoFuture<Output = Result<CallOutcome, CallError>>,
+{
+    let expected = expected.into();
+
+    match call.await {
+        Ok(outcome) => panic!(
+            "{} was expected to fail with {}, but succeeded",
+            outcome.label, expected
+        ),
+        Err(err) => {
+            assert!(
+                expected.is_matched_by(&err),
+                "{} was expected to fail with {}, but got: {}",
+                err.label(),
+                expected,
+                err
+            );
+            log!("{} | FAILED AS EXPECTED ({})", err.label(), expected);
+
+            err
+        }
+    }
+}
+
+/// Pull `<msg>` out of a debug-formatted `ExecutionError("Smart contract panicked: <msg>")`.
+fn extract_panic_message(failure: &str) -> Option<String> {
+    let start = failure.find(PANIC_PREFIX)? + PANIC_PREFIX.len();
+    let mut message = String::new();
+    let mut chars = failure[start..].chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => message.extend(chars.next()),
+            '"' => break,
+            _ => message.push(c),
+        }
+    }
+
+    Some(message)
+}
@@ -0,0 +1,51 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+
+tokio::task_local! {
+    static OUTPUT: Arc<Mutex<String>>;
+}
+
+/// `println!` for everything the tests and clients print, so that tests running in parallel
+/// can capture their output instead of interleaving it.
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::output::print_line(format!($($arg)*))
+    };
+}
+
+/// Append a line to the output captured by the current task, or print it if nothing captures.
+pub fn print_line(line: String) {
+    let captured = OUTPUT.try_with(|output| {
+        let mut output = output.lock().unwrap();
+        output.push_str(&line);
+        output.push('\n');
+    });
+
+    if captured.is_err() {
+        println!("{}", line);
+    }
+}
+
+/// Run `future`, returning its result together with everything it logged.
+pub async fn capture<F: Future>(future: F) -> (F::Output, String) {
+    let output = Arc::new(Mutex::new(String::new()));
+    let result = OUTPUT.scope(output.clone(), future).await;
+    let captured = std::mem::take(&mut *output.lock().unwrap());
+
+    (result, captured)
+}
+
+/// `tokio::spawn` that keeps logging into the capture of the spawning task, if any.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match OUTPUT.try_with(|output| output.clone()) {
+        Ok(output) => tokio::spawn(OUTPUT.scope(output, future)),
+        Err(_) => tokio::spawn(future),
+    }
+}
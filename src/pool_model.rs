@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use near_sdk::{json_types::U128, Balance};
+use workspaces::AccountId;
+
+use crate::*;
+
+/// Epochs after `unstake` before the unstaked balance can be withdrawn.
+pub const NUM_EPOCHS_TO_UNLOCK: u64 = 4;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ModelAccount {
+    unstaked: Balance,
+    stake_shares: u128,
+    unstaked_available_epoch_height: u64,
+}
+
+/// Off-chain model of the staking pool accounting of `staking_farm.wasm`.
+///
+/// Mirrors the contract's math: stake is held as "stake" shares bought and sold at the share
+/// price `total_staked_balance / total_stake_shares`, rounding in the pool's favour; unstaked
+/// balance unlocks `NUM_EPOCHS_TO_UNLOCK` epochs after the last unstake; rewards raise the
+/// share price, and the owner gets `reward_fee_fraction` of them as newly bought shares. A
+/// new reward fee fraction takes effect at the next epoch.
+///
+/// Change methods mirror the `StakingFarmClient` wrappers and fail with the contract's panic
+/// message; views return the same types as the contract so they can be compared with
+/// `compare_account`/`compare_pool_summary`/`compare_with_farm`.
+#[derive(Debug, Clone)]
+pub struct PoolModel {
+    owner_id: AccountId,
+    reward_fee_fraction: Ratio,
+    next_reward_fee_fraction: Ratio,
+    total_staked_balance: Balance,
+    total_stake_shares: u128,
+    epoch_height: u64,
+    accounts: BTreeMap<AccountId, ModelAccount>,
+}
+
+/// A field where the contract differs from the model.
+#[derive(Debug, Clone)]
+pub struct FieldMismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for FieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: model {}, contract {}",
+            self.field, self.expected, self.actual
+        )
+    }
+}
+
+impl PoolModel {
+    /// A freshly initialized pool. `initial_stake` is the stake the contract bought for itself
+    /// at initialization, which nobody owns (`get_total_staked_balance` right after `new`).
+    pub fn new(owner_id: &AccountId, reward_fee_fraction: Ratio, initial_stake: Balance) -> Self {
+        Self {
+            owner_id: owner_id.clone(),
+            next_reward_fee_fraction: reward_fee_fraction.clone(),
+            reward_fee_fraction,
+            total_staked_balance: initial_stake,
+            total_stake_shares: initial_stake,
+            epoch_height: 0,
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    // STAKE METHODS ===========================
+
+    pub fn deposit(&mut self, account_id: &AccountId, amount: Balance) -> Result<(), &'static str> {
+        let mut account = self.account(account_id);
+        account.unstaked += amount;
+        self.save_account(account_id, account);
+
+        Ok(())
+    }
+
+    pub fn deposit_and_stake(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) -> Result<(), &'static str> {
+        let before = self.clone();
+        self.deposit(account_id, amount)?;
+        self.stake(account_id, amount).map_err(|err| {
+            *self = before;
+            err
+        })
+    }
+
+    pub fn stake(&mut self, account_id: &AccountId, amount: Balance) -> Result<(), &'static str> {
+        if amount == 0 {
+            return Err("Staking amount should be positive");
+        }
+
+        let mut account = self.account(account_id);
+
+        let num_shares = self.num_shares_from_staked_amount_rounded_down(amount);
+        if num_shares == 0 {
+            return Err(
+                "The calculated number of \"stake\" shares received for staking should be positive",
+            );
+        }
+
+        let charge_amount = self.staked_amount_from_num_shares_rounded_down(num_shares);
+        if charge_amount == 0 {
+            return Err("Invariant violation. Calculated staked amount must be positive, because \"stake\" share price should be at least 1");
+        }
+        if account.unstaked < charge_amount {
+            return Err("Not enough unstaked balance to stake");
+        }
+
+        account.unstaked -= charge_amount;
+        account.stake_shares += num_shares;
+        self.save_account(account_id, account);
+
+        let stake_amount = self.staked_amount_from_num_shares_rounded_up(num_shares);
+        self.total_staked_balance += stake_amount;
+        self.total_stake_shares += num_shares;
+
+        Ok(())
+    }
+
+    pub fn stake_all(&mut self, account_id: &AccountId) -> Result<(), &'static str> {
+        let amount = self.account(account_id).unstaked;
+        self.stake(account_id, amount)
+    }
+
+    pub fn unstake(&mut self, account_id: &AccountId, amount: Balance) -> Result<(), &'static str> {
+        if amount == 0 {
+            return Err("Unstaking amount should be positive");
+        }
+        if self.total_staked_balance == 0 {
+            return Err("The contract doesn't have staked balance");
+        }
+
+        let mut account = self.account(account_id);
+
+        let num_shares = self.num_shares_from_staked_amount_rounded_up(amount);
+        if num_shares == 0 {
+            return Err("Invariant violation. The calculated number of \"stake\" shares for unstaking should be positive");
+        }
+        if account.stake_shares < num_shares {
+            return Err("Not enough staked balance to unstake");
+        }
+
+        let receive_amount = self.staked_amount_from_num_shares_rounded_up(num_shares);
+        account.stake_shares -= num_shares;
+        account.unstaked += receive_amount;
+        account.unstaked_available_epoch_height = self.epoch_height + NUM_EPOCHS_TO_UNLOCK;
+        self.save_account(account_id, account);
+
+        let unstake_amount = self.staked_amount_from_num_shares_rounded_down(num_shares);
+        self.total_staked_balance -= unstake_amount;
+        self.total_stake_shares -= num_shares;
+
+        Ok(())
+    }
+
+    pub fn unstake_all(&mut self, account_id: &AccountId) -> Result<(), &'static str> {
+        let amount = self.get_account_staked_balance(account_id);
+        self.unstake(account_id, amount)
+    }
+
+    pub fn withdraw(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) -> Result<(), &'static str> {
+        if amount == 0 {
+            return Err("Withdrawal amount should be positive");
+        }
+
+        let mut account = self.account(account_id);
+        if account.unstaked < amount {
+            return Err("Not enough unstaked balance to withdraw");
+        }
+        if account.unstaked_available_epoch_height > self.epoch_height {
+            return Err("The unstaked balance is not yet available due to unstaking delay");
+        }
+
+        account.unstaked -= amount;
+        self.save_account(account_id, account);
+
+        Ok(())
+    }
+
+    pub fn withdraw_all(&mut self, account_id: &AccountId) -> Result<(), &'static str> {
+        let amount = self.account(account_id).unstaked;
+        self.withdraw(account_id, amount)
+    }
+
+    // EPOCHS AND REWARDS ===========================
+
+    /// Move `epochs` epochs forward. The next reward fee fraction takes effect.
+    pub fn new_epochs(&mut self, epochs: u64) {
+        if epochs > 0 {
+            self.epoch_height += epochs;
+            self.reward_fee_fraction = self.next_reward_fee_fraction.clone();
+        }
+    }
+
+    pub fn epoch_height(&self) -> u64 {
+        self.epoch_height
+    }
+
+    /// The pool's stake grew by `amount`, as seen by the next `ping`: the share price goes up
+    /// and the owner buys shares with the reward fee.
+    pub fn distribute_reward(&mut self, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+
+        let owners_fee = mul_div(
+            amount,
+            self.reward_fee_fraction.numerator as u128,
+            self.reward_fee_fraction.denominator as u128,
+        );
+        self.total_staked_balance += amount - owners_fee;
+
+        let num_shares = self.num_shares_from_staked_amount_rounded_down(owners_fee);
+        if num_shares > 0 {
+            let owner_id = self.owner_id.clone();
+            let mut account = self.account(&owner_id);
+            account.stake_shares += num_shares;
+            self.save_account(&owner_id, account);
+            self.total_stake_shares += num_shares;
+        }
+
+        self.total_staked_balance += owners_fee;
+    }
+
+    pub fn update_reward_fee_fraction(&mut self, reward_fee_fraction: Ratio) {
+        self.next_reward_fee_fraction = reward_fee_fraction;
+    }
+
+    // VIEW METHODS ===========================
+
+    pub fn get_account(&self, account_id: &AccountId) -> HumanReadableAccount {
+        let account = self.account(account_id);
+
+        HumanReadableAccount {
+            account_id: account_id.clone(),
+            unstaked_balance: U128(account.unstaked),
+            staked_balance: U128(
+                self.staked_amount_from_num_shares_rounded_down(account.stake_shares),
+            ),
+            can_withdraw: account.unstaked_available_epoch_height <= self.epoch_height,
+        }
+    }
+
+    pub fn get_account_staked_balance(&self, account_id: &AccountId) -> Balance {
+        self.get_account(account_id).staked_balance.0
+    }
+
+    pub fn get_account_unstaked_balance(&self, account_id: &AccountId) -> Balance {
+        self.account(account_id).unstaked
+    }
+
+    /// Every account with a balance, like `get_accounts` with no limit.
+    pub fn get_accounts(&self) -> Vec<HumanReadableAccount> {
+        self.accounts
+            .keys()
+            .map(|account_id| self.get_account(account_id))
+            .collect()
+    }
+
+    pub fn get_total_staked_balance(&self) -> Balance {
+        self.total_staked_balance
+    }
+
+    /// The pool summary, without farms.
+    pub fn get_pool_summary(&self) -> PoolSummary {
+        PoolSummary {
+            owner: self.owner_id.clone(),
+            total_staked_balance: U128(self.total_staked_balance),
+            reward_fee_fraction: self.reward_fee_fraction.clone(),
+            next_reward_fee_fraction: self.next_reward_fee_fraction.clone(),
+            farms: vec![],
+        }
+    }
+
+    // COMPARISON ===========================
+
+    pub fn compare_account(&self, actual: &HumanReadableAccount) -> Vec<FieldMismatch> {
+        let expected = self.get_account(&actual.account_id);
+        let field = |name: &str| format!("{}.{}", actual.account_id, name);
+        let mut mismatches = vec![];
+
+        push_mismatch(
+            &mut mismatches,
+            field("unstaked_balance"),
+            expected.unstaked_balance.0,
+            actual.unstaked_balance.0,
+        );
+        push_mismatch(
+            &mut mismatches,
+            field("staked_balance"),
+            expected.staked_balance.0,
+            actual.staked_balance.0,
+        );
+        push_mismatch(
+            &mut mismatches,
+            field("can_withdraw"),
+            expected.can_withdraw,
+            actual.can_withdraw,
+        );
+
+        mismatches
+    }
+
+    /// Compares everything but the farms.
+    pub fn compare_pool_summary(&self, actual: &PoolSummary) -> Vec<FieldMismatch> {
+        let expected = self.get_pool_summary();
+        let mut mismatches = vec![];
+
+        push_mismatch(
+            &mut mismatches,
+            "pool_summary.owner".to_string(),
+            expected.owner,
+            actual.owner.clone(),
+        );
+        push_mismatch(
+            &mut mismatches,
+            "pool_summary.total_staked_balance".to_string(),
+            expected.total_staked_balance.0,
+            actual.total_staked_balance.0,
+        );
+        push_mismatch(
+            &mut mismatches,
+            "pool_summary.reward_fee_fraction".to_string(),
+            ratio_tuple(&expected.reward_fee_fraction),
+            ratio_tuple(&actual.reward_fee_fraction),
+        );
+        push_mismatch(
+            &mut mismatches,
+            "pool_summary.next_reward_fee_fraction".to_string(),
+            ratio_tuple(&expected.next_reward_fee_fraction),
+            ratio_tuple(&actual.next_reward_fee_fraction),
+        );
+
+        mismatches
+    }
+
+    /// Compare the model with the farm's views: every account either of them knows about,
+    /// the number of accounts and the pool summary.
+    pub async fn compare_with_farm(
+        &self,
+        farm: &StakingFarmClient,
+    ) -> anyhow::Result<Vec<FieldMismatch>> {
+        let mut mismatches = vec![];
+
+        let number_of_accounts = farm.get_number_of_accounts().await?;
+        push_mismatch(
+            &mut mismatches,
+            "number_of_accounts".to_string(),
+            self.accounts.len() as u64,
+            number_of_accounts,
+        );
+
+        let mut account_ids: Vec<AccountId> = self.accounts.keys().cloned().collect();
+        for account in farm.get_accounts(0, number_of_accounts).await? {
+            if !account_ids.contains(&account.account_id) {
+                account_ids.push(account.account_id);
+            }
+        }
+        for account_id in &account_ids {
+            let actual = farm.get_account(account_id).await?;
+            mismatches.extend(self.compare_account(&actual));
+        }
+
+        mismatches.extend(self.compare_pool_summary(&farm.get_pool_summary().await?));
+
+        Ok(mismatches)
+    }
+
+    fn account(&self, account_id: &AccountId) -> ModelAccount {
+        self.accounts.get(account_id).cloned().unwrap_or_default()
+    }
+
+    /// Like the contract, forget accounts without any balance.
+    fn save_account(&mut self, account_id: &AccountId, account: ModelAccount) {
+        if account.unstaked == 0 && account.stake_shares == 0 {
+            self.accounts.remove(account_id);
+        } else {
+            self.accounts.insert(account_id.clone(), account);
+        }
+    }
+
+    fn num_shares_from_staked_amount_rounded_down(&self, amount: Balance) -> u128 {
+        if self.total_staked_balance == 0 {
+            return amount;
+        }
+        mul_div(self.total_stake_shares, amount, self.total_staked_balance)
+    }
+
+    fn num_shares_from_staked_amount_rounded_up(&self, amount: Balance) -> u128 {
+        if self.total_staked_balance == 0 {
+            return amount;
+        }
+        mul_div_ceil(self.total_stake_shares, amount, self.total_staked_balance)
+    }
+
+    fn staked_amount_from_num_shares_rounded_down(&self, num_shares: u128) -> Balance {
+        if self.total_stake_shares == 0 {
+            return num_shares;
+        }
+        mul_div(
+            self.total_staked_balance,
+            num_shares,
+            self.total_stake_shares,
+        )
+    }
+
+    fn staked_amount_from_num_shares_rounded_up(&self, num_shares: u128) -> Balance {
+        if self.total_stake_shares == 0 {
+            return num_shares;
+        }
+        mul_div_ceil(
+            self.total_staked_balance,
+            num_shares,
+            self.total_stake_shares,
+        )
+    }
+}
+
+fn ratio_tuple(ratio: &Ratio) -> (u32, u32) {
+    (ratio.numerator, ratio.denominator)
+}
+
+fn push_mismatch<T: PartialEq + fmt::Debug>(
+    mismatches: &mut Vec<FieldMismatch>,
+    field: String,
+    expected: T,
+    actual: T,
+) {
+    if expected != actual {
+        mismatches.push(FieldMismatch {
+            field,
+            expected: format!("{:?}", expected),
+            actual: format!("{:?}", actual),
+        });
+    }
+}
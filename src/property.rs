@@ -0,0 +1,435 @@
+use std::collections::BTreeMap;
+
+use near_sdk::Balance;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use workspaces::AccountId;
+
+use crate::*;
+
+const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+/// Kept in every wallet for gas, never deposited.
+const WALLET_RESERVE: Balance = 10 * ONE_NEAR;
+
+/// Settings of a property test run.
+#[derive(Debug, Clone)]
+pub struct PropertyConfig {
+    pub seed: u64,
+    /// Number of random sequences.
+    pub cases: u64,
+    pub steps: usize,
+    pub users: usize,
+    pub user_balance: Balance,
+    /// The largest amount a single action moves.
+    pub max_amount: Balance,
+    /// How many sequences shrinking may run before giving up on a smaller one.
+    pub max_shrink_runs: usize,
+}
+
+impl Default for PropertyConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            cases: 10,
+            steps: 20,
+            users: 3,
+            user_balance: 10_000 * ONE_NEAR,
+            max_amount: 2_000 * ONE_NEAR,
+            max_shrink_runs: 50,
+        }
+    }
+}
+
+/// A sequence that broke the farm, shrunk as far as the shrink budget allowed.
+#[derive(Debug, Clone)]
+pub struct PropertyFailure {
+    pub seed: u64,
+    pub error: String,
+    /// The shrunk sequence as a scenario that reproduces the failure.
+    pub scenario: Scenario,
+}
+
+/// What the model expects from an action.
+#[derive(Debug, Clone, PartialEq)]
+enum Expected {
+    Success,
+    Failure(&'static str),
+    /// Depends on things the model doesn't track, e.g. whether the farm's own unstaked balance
+    /// in the validator is unlocked. Such sequences are not generated.
+    Undetermined,
+}
+
+/// Expected farm balances and `can_withdraw` per user, plus their wallets.
+#[derive(Debug, Clone)]
+struct BalanceModel {
+    pool: PoolModel,
+    wallets: BTreeMap<String, Balance>,
+    /// Epoch from which the farm can withdraw its unstaked balance from the validator.
+    farm_unlock_epoch: u64,
+}
+
+impl BalanceModel {
+    fn new(users: &[String], user_balance: Balance) -> Self {
+        let owner_id: AccountId = "owner".parse().unwrap();
+
+        Self {
+            // The sandbox validator gives no rewards, so the farm's own stake doesn't change
+            // the share price and needn't be known.
+            pool: PoolModel::new(
+                &owner_id,
+                Ratio {
+                    numerator: 1,
+                    denominator: 2,
+                },
+                0,
+            ),
+            wallets: users
+                .iter()
+                .map(|user| (user.clone(), user_balance))
+                .collect(),
+            farm_unlock_epoch: 0,
+        }
+    }
+
+    fn account(&self, user: &str) -> HumanReadableAccount {
+        self.pool.get_account(&user_id(user))
+    }
+
+    fn wallet(&self, user: &str) -> Balance {
+        self.wallets.get(user).copied().unwrap_or_default()
+    }
+
+    /// Apply `action` if the model expects it to succeed.
+    fn apply(&mut self, action: &Action) -> Expected {
+        let mut pool = self.pool.clone();
+
+        let res = match action {
+            Action::Deposit { account, amount } | Action::DepositAndStake { account, amount } => {
+                if amount.0 + WALLET_RESERVE > self.wallet(account) {
+                    return Expected::Undetermined;
+                }
+
+                let res = if matches!(action, Action::Deposit { .. }) {
+                    pool.deposit(&user_id(account), amount.0)
+                } else {
+                    pool.deposit_and_stake(&user_id(account), amount.0)
+                };
+                if res.is_ok() {
+                    *self.wallets.get_mut(account).unwrap() -= amount.0;
+                }
+                res
+            }
+            Action::Stake { account, amount } => pool.stake(&user_id(account), amount.0),
+            Action::StakeAll { account } => pool.stake_all(&user_id(account)),
+            Action::Unstake { account, .. } | Action::UnstakeAll { account } => {
+                let amount = match action {
+                    Action::Unstake { amount, .. } => amount.0,
+                    _ => self.account(account).staked_balance.0,
+                };
+                if amount > 0 && pool.get_accounts().iter().all(|a| a.staked_balance.0 == 0) {
+                    // Fails unless the farm has stake of its own.
+                    return Expected::Undetermined;
+                }
+
+                let res = pool.unstake(&user_id(account), amount);
+                if res.is_ok() {
+                    self.farm_unlock_epoch = pool.epoch_height() + NUM_EPOCHS_TO_UNLOCK;
+                }
+                res
+            }
+            Action::Withdraw { account, .. } | Action::WithdrawAll { account } => {
+                let amount = match action {
+                    Action::Withdraw { amount, .. } => amount.0,
+                    _ => self.account(account).unstaked_balance.0,
+                };
+
+                let res = pool.withdraw(&user_id(account), amount);
+                if res.is_ok() {
+                    if pool.epoch_height() < self.farm_unlock_epoch {
+                        return Expected::Undetermined;
+                    }
+                    *self.wallets.get_mut(account).unwrap() += amount;
+                }
+                res
+            }
+            Action::WaitEpochs { epochs } => {
+                pool.new_epochs(*epochs);
+                Ok(())
+            }
+            _ => return Expected::Undetermined,
+        };
+
+        match res {
+            Ok(()) => {
+                self.pool = pool;
+                Expected::Success
+            }
+            Err(message) => Expected::Failure(message),
+        }
+    }
+}
+
+fn user_id(user: &str) -> AccountId {
+    user.parse().unwrap()
+}
+
+/// Random sequences of staking actions by several users, checked against a model of their
+/// expected balances and `can_withdraw` after every step (and against the
+/// `InvariantChecker`). A failing sequence is shrunk and returned as a scenario that
+/// reproduces it.
+pub struct PropertyTest<'a> {
+    fixture: &'a Fixture,
+    config: PropertyConfig,
+    users: Vec<String>,
+    runs: usize,
+}
+
+impl<'a> PropertyTest<'a> {
+    pub fn new(fixture: &'a Fixture, config: PropertyConfig) -> Self {
+        let users = (0..config.users).map(|i| format!("u{}", i)).collect();
+
+        Self {
+            fixture,
+            config,
+            users,
+            runs: 0,
+        }
+    }
+
+    /// Run `config.cases` sequences, stopping at the first one that fails.
+    pub async fn run(&mut self) -> anyhow::Result<Option<PropertyFailure>> {
+        for case in 0..self.config.cases {
+            let seed = self.config.seed + case;
+            let actions = self.generate(seed);
+
+            log!(
+                "Property case {} (seed {}), {} actions",
+                case,
+                seed,
+                actions.len()
+            );
+
+            if let Err(err) = self.execute(&actions).await? {
+                log!("Seed {} failed, shrinking: {}", seed, err);
+                return Ok(Some(self.shrink(seed, actions, err).await?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// A random sequence the model can predict every outcome of.
+    fn generate(&self, seed: u64) -> Vec<Action> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut model = BalanceModel::new(&self.users, self.config.user_balance);
+        let mut actions = vec![];
+
+        while actions.len() < self.config.steps {
+            let user = self.users[rng.gen_range(0..self.users.len())].clone();
+            let action = self.random_action(&mut rng, &model, user);
+
+            if model.apply(&action) != Expected::Undetermined {
+                actions.push(action);
+            }
+        }
+
+        actions
+    }
+
+    fn random_action(&self, rng: &mut StdRng, model: &BalanceModel, account: String) -> Action {
+        let kind = rng.gen_range(0..100);
+        let epochs = rng.gen_range(1..=NUM_EPOCHS_TO_UNLOCK);
+        let state = model.account(&account);
+        let max_deposit = model
+            .wallet(&account)
+            .saturating_sub(WALLET_RESERVE)
+            .min(self.config.max_amount);
+        // Mostly amounts that fit, sometimes more than there is to hit the error paths.
+        let mut amount = |available: Balance| {
+            let available_near = available / ONE_NEAR;
+            let max_near = self.config.max_amount / ONE_NEAR;
+            let near = if available_near > 0 && rng.gen_bool(0.9) {
+                rng.gen_range(1..=available_near)
+            } else {
+                rng.gen_range(available_near + 1..=available_near + max_near.max(1))
+            };
+            Amount(near * ONE_NEAR)
+        };
+
+        match kind {
+            0..=14 => Action::Deposit {
+                amount: amount(max_deposit),
+                account,
+            },
+            15..=24 => Action::DepositAndStake {
+                amount: amount(max_deposit),
+                account,
+            },
+            25..=39 => Action::Stake {
+                amount: amount(state.unstaked_balance.0),
+                account,
+            },
+            40..=44 => Action::StakeAll { account },
+            45..=59 => Action::Unstake {
+                amount: amount(state.staked_balance.0),
+                account,
+            },
+            60..=64 => Action::UnstakeAll { account },
+            65..=79 => Action::Withdraw {
+                amount: amount(state.unstaked_balance.0),
+                account,
+            },
+            80..=91 => Action::WithdrawAll { account },
+            _ => Action::WaitEpochs { epochs },
+        }
+    }
+
+    /// The sequence as a scenario with the model's expectations after every step. `None` if
+    /// the model can't predict some step.
+    fn to_scenario(&self, name: &str, actions: &[Action]) -> Option<Scenario> {
+        let mut model = BalanceModel::new(&self.users, self.config.user_balance);
+        let mut steps = vec![];
+
+        for action in actions {
+            let step = match model.apply(action) {
+                Expected::Undetermined => return None,
+                Expected::Failure(message) => Step {
+                    action: action.clone(),
+                    expect_failure: Some(message.to_string()),
+                    expect: None,
+                },
+                Expected::Success => Step {
+                    action: action.clone(),
+                    expect_failure: None,
+                    expect: Some(Expectations {
+                        accounts: self
+                            .users
+                            .iter()
+                            .map(|user| {
+                                let state = model.account(user);
+                                let expected = ExpectedAccount {
+                                    staked_balance: Some(Amount(state.staked_balance.0)),
+                                    unstaked_balance: Some(Amount(state.unstaked_balance.0)),
+                                    can_withdraw: Some(state.can_withdraw),
+                                };
+                                (user.clone(), expected)
+                            })
+                            .collect(),
+                        ..Default::default()
+                    }),
+                },
+            };
+            steps.push(step);
+        }
+
+        Some(Scenario {
+            name: name.to_string(),
+            accounts: self
+                .users
+                .iter()
+                .map(|user| ScenarioAccount {
+                    name: user.clone(),
+                    balance: Amount(self.config.user_balance),
+                })
+                .collect(),
+            steps,
+            check_invariants: true,
+        })
+    }
+
+    /// Run the sequence in a fresh farm. The outer error is for the harness failing, the inner
+    /// one for the farm not behaving like the model.
+    async fn execute(&mut self, actions: &[Action]) -> anyhow::Result<Result<(), String>> {
+        // every run gets its own env, named after the run
+        let name = format!("p{}-{}", self.config.seed, self.runs);
+        self.runs += 1;
+
+        let scenario = self
+            .to_scenario(&name, actions)
+            .ok_or_else(|| anyhow::anyhow!("the model can't predict the sequence"))?;
+        let env = self.fixture.env(&name).await?;
+
+        let res = run_scenario(&env, &scenario).await;
+
+        Ok(res.map_err(|err| format!("{:#}", err)))
+    }
+
+    /// Drop chunks of actions, then halve amounts, as long as the sequence keeps failing.
+    async fn shrink(
+        &mut self,
+        seed: u64,
+        mut actions: Vec<Action>,
+        mut error: String,
+    ) -> anyhow::Result<PropertyFailure> {
+        let mut shrink_runs = 0;
+        let mut chunk = std::cmp::max(actions.len() / 2, 1);
+
+        loop {
+            let mut i = 0;
+            while i < actions.len() && shrink_runs < self.config.max_shrink_runs {
+                let mut candidate = actions.clone();
+                candidate.drain(i..std::cmp::min(i + chunk, actions.len()));
+
+                if self.to_scenario("candidate", &candidate).is_some() {
+                    shrink_runs += 1;
+                    if let Err(err) = self.execute(&candidate).await? {
+                        actions = candidate;
+                        error = err;
+                        continue;
+                    }
+                }
+                i += chunk;
+            }
+
+            if chunk == 1 || shrink_runs >= self.config.max_shrink_runs {
+                break;
+            }
+            chunk /= 2;
+        }
+
+        for i in 0..actions.len() {
+            while shrink_runs < self.config.max_shrink_runs {
+                let mut candidate = actions.clone();
+                match action_amount(&mut candidate[i]) {
+                    Some(amount) if amount.0 > ONE_NEAR => {
+                        amount.0 = (amount.0 / ONE_NEAR / 2).max(1) * ONE_NEAR
+                    }
+                    _ => break,
+                }
+
+                if self.to_scenario("candidate", &candidate).is_none() {
+                    break;
+                }
+                shrink_runs += 1;
+                match self.execute(&candidate).await? {
+                    Err(err) => {
+                        actions = candidate;
+                        error = err;
+                    }
+                    Ok(()) => break,
+                }
+            }
+        }
+
+        let scenario = self
+            .to_scenario(&format!("prop-{}", seed), &actions)
+            .expect("only predictable sequences are kept");
+
+        Ok(PropertyFailure {
+            seed,
+            error,
+            scenario,
+        })
+    }
+}
+
+fn action_amount(action: &mut Action) -> Option<&mut Amount> {
+    match action {
+        Action::Deposit { amount, .. }
+        | Action::DepositAndStake { amount, .. }
+        | Action::Stake { amount, .. }
+        | Action::Unstake { amount, .. }
+        | Action::Withdraw { amount, .. } => Some(amount),
+        _ => None,
+    }
+}
@@ -0,0 +1,246 @@
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use tokio::task::JoinSet;
+
+use crate::*;
+
+pub type TestFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+pub type TestFn = Arc<dyn Fn(TestEnv) -> TestFuture + Send + Sync>;
+
+/// What has to be in place before a test can run.
+#[derive(Debug, Clone)]
+pub enum Requirement {
+    /// A wasm file that is built separately, e.g. one of the mock contracts.
+    Wasm(&'static str),
+}
+
+#[derive(Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub requires: Vec<Requirement>,
+    run: TestFn,
+}
+
+impl TestCase {
+    pub fn new<F, Fut>(name: &str, run: F) -> Self
+    where
+        F: Fn(TestEnv) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            tags: vec![],
+            requires: vec![],
+            run: Arc::new(move |env| Box::pin(run(env))),
+        }
+    }
+
+    pub fn tags(mut self, tags: &[&str]) -> Self {
+        self.tags.extend(tags.iter().map(|tag| tag.to_string()));
+        self
+    }
+
+    pub fn requires(mut self, requirement: Requirement) -> Self {
+        self.requires.push(requirement);
+        self
+    }
+
+    fn missing_wasm(&self) -> Option<&'static str> {
+        self.requires
+            .iter()
+            .find_map(|requirement| match requirement {
+                Requirement::Wasm(path) if !Path::new(path).exists() => Some(*path),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TestStatus {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TestReport {
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+}
+
+/// Turn a `*` wildcard pattern into an anchored regex.
+fn glob_to_regex(pattern: &str) -> Regex {
+    let escaped = regex::escape(pattern).replace(r"\*", ".*");
+    Regex::new(&format!("^{}$", escaped)).expect("escaped pattern is a valid regex")
+}
+
+/// Tests matching any of the name patterns and any of the tag patterns (an empty list matches
+/// everything), in registration order.
+pub fn select_tests<'a>(
+    tests: &'a [TestCase],
+    patterns: &[String],
+    tags: &[String],
+) -> Vec<&'a TestCase> {
+    let patterns: Vec<Regex> = patterns.iter().map(|p| glob_to_regex(p)).collect();
+    let tags: Vec<Regex> = tags.iter().map(|t| glob_to_regex(t)).collect();
+
+    tests
+        .iter()
+        .filter(|test| {
+            let name_matches =
+                patterns.is_empty() || patterns.iter().any(|p| p.is_match(&test.name));
+            let tag_matches = tags.is_empty()
+                || test
+                    .tags
+                    .iter()
+                    .any(|tag| tags.iter().any(|t| t.is_match(tag)));
+
+            name_matches && tag_matches
+        })
+        .collect()
+}
+
+/// Run tests on up to `jobs` tasks at once, each in its own `TestEnv`, continuing after
+/// failures unless `fail_fast`. With more than one job the output of every test is captured
+/// and printed in one piece when the test finishes. Reports are in the order of `tests`.
+pub async fn run_tests(
+    fixture: Arc<Fixture>,
+    tests: &[&TestCase],
+    jobs: usize,
+    fail_fast: bool,
+) -> Vec<TestReport> {
+    let jobs = std::cmp::max(jobs, 1);
+    let capture_output = jobs > 1;
+
+    let mut reports: Vec<TestReport> = vec![];
+    let mut running = JoinSet::new();
+    let mut failed = false;
+
+    for test in tests {
+        // wait for a free slot first, so a failure of the tests running then stops this one
+        while running.len() >= jobs {
+            let report = finish_test(running.join_next().await);
+            failed |= matches!(report.status, TestStatus::Failed(_));
+            reports.push(report);
+        }
+
+        if failed && fail_fast {
+            break;
+        }
+
+        if let Some(path) = test.missing_wasm() {
+            reports.push(TestReport {
+                name: test.name.clone(),
+                status: TestStatus::Skipped(format!("{} is not built", path)),
+                duration: Duration::ZERO,
+            });
+            continue;
+        }
+
+        let fixture = fixture.clone();
+        let test = (*test).clone();
+
+        running.spawn(async move {
+            let started = Instant::now();
+            let (status, output) = if capture_output {
+                capture(run_test(&fixture, &test)).await
+            } else {
+                (run_test(&fixture, &test).await, String::new())
+            };
+
+            let report = TestReport {
+                name: test.name,
+                status,
+                duration: started.elapsed(),
+            };
+            (report, output)
+        });
+    }
+
+    while !running.is_empty() {
+        reports.push(finish_test(running.join_next().await));
+    }
+
+    reports.sort_by_key(|report| tests.iter().position(|test| test.name == report.name));
+    reports
+}
+
+fn finish_test(joined: Option<Result<(TestReport, String), tokio::task::JoinError>>) -> TestReport {
+    let (report, output) = joined
+        .expect("only called while tests are running")
+        .expect("test panics are caught by run_test");
+
+    if !output.is_empty() {
+        print!("{}", output);
+    }
+
+    report
+}
+
+/// Set up a fresh `TestEnv` and run a single test on its own task, so a panicking assertion
+/// fails only this test.
+pub async fn run_test(fixture: &Fixture, test: &TestCase) -> TestStatus {
+    log!("\n=== RUN {}", test.name);
+
+    let env = match fixture.env(&test.name).await {
+        Ok(env) => env,
+        Err(err) => return TestStatus::Failed(format!("setup failed: {:#}", err)),
+    };
+
+    match spawn((test.run)(env)).await {
+        Ok(Ok(())) => TestStatus::Passed,
+        Ok(Err(err)) => TestStatus::Failed(format!("{:#}", err)),
+        Err(err) if err.is_panic() => {
+            let panic = err.into_panic();
+            let message = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "panicked".to_string());
+
+            TestStatus::Failed(message)
+        }
+        Err(err) => TestStatus::Failed(err.to_string()),
+    }
+}
+
+/// Print one line per test and the totals. Returns whether nothing failed.
+pub fn print_summary(reports: &[TestReport]) -> bool {
+    println!("\nSUMMARY");
+
+    for report in reports {
+        let (label, detail) = match &report.status {
+            TestStatus::Passed => ("PASS", None),
+            TestStatus::Failed(err) => ("FAIL", Some(err)),
+            TestStatus::Skipped(reason) => ("SKIP", Some(reason)),
+        };
+        println!(
+            "{} {} ({:.1}s)",
+            label,
+            report.name,
+            report.duration.as_secs_f64()
+        );
+        if let Some(detail) = detail {
+            println!("     {}", detail.replace('\n', "\n     "));
+        }
+    }
+
+    let count = |f: fn(&TestStatus) -> bool| reports.iter().filter(|r| f(&r.status)).count();
+    let passed = count(|s| matches!(s, TestStatus::Passed));
+    let failed = count(|s| matches!(s, TestStatus::Failed(_)));
+    let skipped = count(|s| matches!(s, TestStatus::Skipped(_)));
+
+    println!(
+        "\n{} passed, {} failed, {} skipped",
+        passed, failed, skipped
+    );
+
+    failed == 0
+}
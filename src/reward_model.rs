@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use near_sdk::Balance;
+use workspaces::AccountId;
+
+use crate::types::*;
+
+/// Farm rewards are released once per session.
+pub const SESSION_INTERVAL: u64 = 1_000_000_000;
+/// Precision of the reward per share.
+const DENOMINATOR: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// The part of a farm that defines how its rewards are released.
+#[derive(Debug, Clone)]
+pub struct FarmParams {
+    pub amount: Balance,
+    pub start_date: u64,
+    pub end_date: u64,
+}
+
+impl From<&HumanReadableFarm> for FarmParams {
+    fn from(farm: &HumanReadableFarm) -> Self {
+        Self {
+            amount: farm.amount.0,
+            start_date: farm.start_date.0,
+            end_date: farm.end_date.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StakeEvent {
+    Stake {
+        account_id: AccountId,
+        amount: Balance,
+    },
+    Unstake {
+        account_id: AccountId,
+        amount: Balance,
+    },
+    /// The farm's stake in the validator grew by `amount`, the owner takes the reward fee in
+    /// stake shares.
+    ValidatorReward { amount: Balance },
+}
+
+/// Off-chain model of how `staking_farm.wasm` splits a farm between stakers.
+///
+/// Every session the farm releases `amount / (end_date - start_date) * SESSION_INTERVAL`
+/// tokens, which are split between accounts pro rata to their stake shares. Accounts own
+/// shares through `Stake`/`Unstake` events, the owner also gets shares as the reward fee on
+/// validator rewards.
+#[derive(Debug, Clone)]
+pub struct RewardModel {
+    farm: FarmParams,
+    owner_id: AccountId,
+    reward_fee_fraction: Ratio,
+    unowned_stake: Balance,
+    events: Vec<(u64, StakeEvent)>,
+}
+
+impl RewardModel {
+    /// Panics if the farm doesn't end after it starts, which the contract rejects with
+    /// `ERR_FARM_DATE`.
+    pub fn new(farm: FarmParams, owner_id: &AccountId, reward_fee_fraction: Ratio) -> Self {
+        assert!(
+            farm.end_date > farm.start_date,
+            "farm ends at {} before it starts at {}",
+            farm.end_date,
+            farm.start_date
+        );
+
+        Self {
+            farm,
+            owner_id: owner_id.clone(),
+            reward_fee_fraction,
+            unowned_stake: 0,
+            events: vec![],
+        }
+    }
+
+    /// Stake no account owns, e.g. the balance the contract staked at initialization. It
+    /// dilutes farm rewards like any other stake.
+    pub fn unowned_stake(mut self, amount: Balance) -> Self {
+        self.unowned_stake = amount;
+        self
+    }
+
+    pub fn stake(mut self, timestamp: u64, account_id: &AccountId, amount: Balance) -> Self {
+        self.events.push((
+            timestamp,
+            StakeEvent::Stake {
+                account_id: account_id.clone(),
+                amount,
+            },
+        ));
+        self
+    }
+
+    pub fn unstake(mut self, timestamp: u64, account_id: &AccountId, amount: Balance) -> Self {
+        self.events.push((
+            timestamp,
+            StakeEvent::Unstake {
+                account_id: account_id.clone(),
+                amount,
+            },
+        ));
+        self
+    }
+
+    pub fn validator_reward(mut self, timestamp: u64, amount: Balance) -> Self {
+        self.events
+            .push((timestamp, StakeEvent::ValidatorReward { amount }));
+        self
+    }
+
+    /// Seed the model with the staked balances of existing accounts at `timestamp`, assuming
+    /// the share price is still 1.
+    pub fn with_accounts(mut self, timestamp: u64, accounts: &[HumanReadableAccount]) -> Self {
+        for account in accounts.iter().filter(|a| a.staked_balance.0 > 0) {
+            self = self.stake(timestamp, &account.account_id, account.staked_balance.0);
+        }
+        self
+    }
+
+    /// Expected `get_unclaimed_reward` of every account that ever staked, at `timestamp`.
+    pub fn unclaimed_rewards(&self, timestamp: u64) -> HashMap<AccountId, Balance> {
+        let mut state = ModelState::new(&self.farm, self.unowned_stake);
+        let mut events = self.events.clone();
+        events.sort_by_key(|(event_timestamp, _)| *event_timestamp);
+
+        for (event_timestamp, event) in events.iter().filter(|(t, _)| *t <= timestamp) {
+            state.distribute(*event_timestamp);
+
+            match event {
+                StakeEvent::Stake { account_id, amount } => {
+                    let shares = state.shares_from_amount(*amount);
+                    state.add_shares(account_id, shares);
+                    state.total_staked_balance += amount;
+                }
+                StakeEvent::Unstake { account_id, amount } => {
+                    let shares = state.shares_from_amount(*amount);
+                    state.remove_shares(account_id, shares);
+                    state.total_staked_balance -= amount;
+                }
+                StakeEvent::ValidatorReward { amount } => {
+                    state.total_staked_balance += amount;
+                    let fee = mul_div(
+                        *amount,
+                        self.reward_fee_fraction.numerator as u128,
+                        self.reward_fee_fraction.denominator as u128,
+                    );
+                    let shares = state.shares_from_amount(fee);
+                    state.add_shares(&self.owner_id, shares);
+                }
+            }
+        }
+
+        state.distribute(timestamp);
+
+        state
+            .accounts
+            .keys()
+            .map(|account_id| (account_id.clone(), state.unclaimed(account_id)))
+            .collect()
+    }
+
+    pub fn unclaimed_reward(&self, account_id: &AccountId, timestamp: u64) -> Balance {
+        self.unclaimed_rewards(timestamp)
+            .get(account_id)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct AccountState {
+    shares: u128,
+    last_reward_per_share: u128,
+    accrued: Balance,
+}
+
+struct ModelState<'a> {
+    farm: &'a FarmParams,
+    total_staked_balance: Balance,
+    total_shares: u128,
+    accounts: HashMap<AccountId, AccountState>,
+    undistributed: Balance,
+    reward_per_share: u128,
+    reward_round: u64,
+}
+
+impl<'a> ModelState<'a> {
+    fn new(farm: &'a FarmParams, unowned_stake: Balance) -> Self {
+        Self {
+            farm,
+            total_staked_balance: unowned_stake,
+            total_shares: unowned_stake,
+            accounts: HashMap::new(),
+            undistributed: farm.amount,
+            reward_per_share: 0,
+            reward_round: 0,
+        }
+    }
+
+    /// Release the rewards of all sessions finished by `timestamp`.
+    fn distribute(&mut self, timestamp: u64) {
+        if timestamp < self.farm.start_date || self.undistributed == 0 {
+            return;
+        }
+
+        let round = (timestamp - self.farm.start_date) / SESSION_INTERVAL;
+        let duration = self.farm.end_date - self.farm.start_date;
+        let reward_per_session = self.farm.amount / duration as u128 * SESSION_INTERVAL as u128;
+        let reward_added = std::cmp::min(
+            (round - self.reward_round) as u128 * reward_per_session,
+            self.undistributed,
+        );
+
+        self.undistributed -= reward_added;
+        self.reward_round = round;
+
+        if self.total_shares > 0 {
+            self.reward_per_share += mul_div(reward_added, DENOMINATOR, self.total_shares);
+        }
+    }
+
+    fn shares_from_amount(&self, amount: Balance) -> u128 {
+        if self.total_staked_balance == 0 {
+            amount
+        } else {
+            mul_div(amount, self.total_shares, self.total_staked_balance)
+        }
+    }
+
+    fn settle(&mut self, account_id: &AccountId) -> &mut AccountState {
+        let reward_per_share = self.reward_per_share;
+        let account = self.accounts.entry(account_id.clone()).or_default();
+
+        account.accrued += mul_div(
+            reward_per_share - account.last_reward_per_share,
+            account.shares,
+            DENOMINATOR,
+        );
+        account.last_reward_per_share = reward_per_share;
+
+        account
+    }
+
+    fn add_shares(&mut self, account_id: &AccountId, shares: u128) {
+        self.settle(account_id).shares += shares;
+        self.total_shares += shares;
+    }
+
+    fn remove_shares(&mut self, account_id: &AccountId, shares: u128) {
+        self.settle(account_id).shares -= shares;
+        self.total_shares -= shares;
+    }
+
+    fn unclaimed(&self, account_id: &AccountId) -> Balance {
+        let account = &self.accounts[account_id];
+
+        account.accrued
+            + mul_div(
+                self.reward_per_share - account.last_reward_per_share,
+                account.shares,
+                DENOMINATOR,
+            )
+    }
+}
+
+/// `a * b / c` rounded down, with a 256-bit intermediate product. The result must fit u128.
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    mul_div_rem(a, b, c).0
+}
+
+/// `a * b / c` rounded up, like `mul_div`.
+pub fn mul_div_ceil(a: u128, b: u128, c: u128) -> u128 {
+    match mul_div_rem(a, b, c) {
+        (quotient, 0) => quotient,
+        (quotient, _) => quotient + 1,
+    }
+}
+
+/// Quotient and remainder of `a * b / c`. Panics if `c` is 0.
+fn mul_div_rem(a: u128, b: u128, c: u128) -> (u128, u128) {
+    assert!(c > 0, "mul_div by zero");
+
+    let mask = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & mask);
+    let (b_hi, b_lo) = (b >> 64, b & mask);
+
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let mid = (lo_lo >> 64) + (hi_lo & mask) + (lo_hi & mask);
+    let lo = (lo_lo & mask) | (mid << 64);
+    let hi = a_hi * b_hi + (hi_lo >> 64) + (lo_hi >> 64) + (mid >> 64);
+
+    let mut quotient = 0u128;
+    let mut remainder = 0u128;
+
+    for bit in (0..256).rev() {
+        let next = if bit >= 128 {
+            (hi >> (bit - 128)) & 1
+        } else {
+            (lo >> bit) & 1
+        };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | next;
+
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            if bit < 128 {
+                quotient |= 1 << bit;
+            }
+        }
+    }
+
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_rem_matches_u128() {
+        let values = [
+            0,
+            1,
+            2,
+            3,
+            7,
+            1_000_000_007,
+            u64::MAX as u128,
+            u64::MAX as u128 + 1,
+        ];
+
+        for &a in &values {
+            for &b in &values {
+                let product = match a.checked_mul(b) {
+                    Some(product) => product,
+                    None => continue,
+                };
+                for &c in values.iter().filter(|c| **c > 0) {
+                    assert_eq!(
+                        mul_div_rem(a, b, c),
+                        (product / c, product % c),
+                        "{} * {} / {}",
+                        a,
+                        b,
+                        c
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mul_div_rem_past_u128() {
+        let max = u128::MAX;
+        let half = 1 << 127;
+
+        // products above u128::MAX with results that fit u128
+        assert_eq!(mul_div_rem(max, max, max), (max, 0));
+        assert_eq!(mul_div_rem(max - 1, max, max), (max - 1, 0));
+        assert_eq!(mul_div_rem(max, 5, max), (5, 0));
+        assert_eq!(
+            mul_div_rem(max, 3, 4),
+            (255211775190703847597530955573826158591, 1)
+        );
+        assert_eq!(
+            mul_div_rem(1 << 64, 1 << 64, 3),
+            (113427455640312821154458202477256070485, 1)
+        );
+        // divisor above 2^127, where the shifted remainder carries out of u128
+        assert_eq!(mul_div_rem(half, half, half + 1), (half - 1, 1));
+    }
+
+    #[test]
+    fn mul_div_rounding() {
+        assert_eq!(mul_div(7, 3, 2), 10);
+        assert_eq!(mul_div_ceil(7, 3, 2), 11);
+        assert_eq!(mul_div_ceil(8, 3, 2), 12);
+        assert_eq!(
+            mul_div_ceil(u128::MAX, 3, 4),
+            255211775190703847597530955573826158592
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "mul_div by zero")]
+    fn mul_div_by_zero() {
+        mul_div(1, 1, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "before it starts")]
+    fn farm_ending_at_start_is_rejected() {
+        let alice: AccountId = "alice.test.near".parse().unwrap();
+        let farm = FarmParams {
+            amount: 1000,
+            start_date: 10 * SESSION_INTERVAL,
+            end_date: 10 * SESSION_INTERVAL,
+        };
+        RewardModel::new(
+            farm,
+            &alice,
+            Ratio {
+                numerator: 0,
+                denominator: 1,
+            },
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use near_sdk::Balance;
+
+use crate::*;
+
+/// Balances moved by `simulate_validator_reward`.
+#[derive(Debug, Clone)]
+pub struct SimulatedReward {
+    /// Transferred into the validator account.
+    pub validator_reward: Balance,
+    /// Growth of the farm's total balance in the validator, i.e. the reward the farm
+    /// distributes on its next `ping`.
+    pub farm_reward: Balance,
+}
+
+/// Simulate an epoch of validator rewards, which the sandbox never pays.
+///
+/// Transfers `amount` from `funder` into the validator account and waits for the next epoch.
+/// The validator's `ping` then takes the balance increase as rewards: it keeps its reward fee
+/// and raises the share price of all its delegators, including the farm. The farm's `ping`
+/// passes its part on to the farm's accounts, minus the farm owner's `reward_fee_fraction`.
+pub async fn simulate_validator_reward(
+    worker: &Worker<Sandbox>,
+    funder: &Account,
+    validator_contract: &Contract,
+    staking_farm_contract: &Contract,
+    amount: Balance,
+) -> anyhow::Result<SimulatedReward> {
+    let validator = ValidatorPoolClient::new(validator_contract, funder);
+    let farm = StakingFarmClient::new(staking_farm_contract, funder);
+
+    let before = validator.get_account_total_balance(farm.id()).await?;
+
+    log!("Transferring {} to validator {}", amount, validator.id());
+    funder
+        .transfer_near(validator.id(), amount)
+        .await?
+        .into_result()?;
+
+    wait_epochs(worker, 1).await?;
+    validator.ping().await?;
+
+    let after = validator.get_account_total_balance(farm.id()).await?;
+    farm.ping().await?;
+
+    let reward = SimulatedReward {
+        validator_reward: amount,
+        farm_reward: after - before,
+    };
+    log!("Simulated reward: {:?}", reward);
+
+    Ok(reward)
+}
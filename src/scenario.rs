@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use near_sdk::{
+    serde::{de, Deserialize, Deserializer, Serialize, Serializer},
+    Balance,
+};
+
+use workspaces::AccountId;
+
+use crate::*;
+
+/// A test case described as data: the accounts it needs, the actions to run and the state
+/// expected after each action.
+///
+/// Files ending with `.json` are read as JSON, anything else as YAML. See `scenarios/` for
+/// examples.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub accounts: Vec<ScenarioAccount>,
+    pub steps: Vec<Step>,
+    /// Run the `InvariantChecker` after every step.
+    #[serde(default = "default_check_invariants")]
+    pub check_invariants: bool,
+}
+
+fn default_check_invariants() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScenarioAccount {
+    pub name: String,
+    pub balance: Amount,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Step {
+    #[serde(flatten)]
+    pub action: Action,
+    /// The action must fail with a message containing this text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect_failure: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<Expectations>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde", tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Deposit {
+        account: String,
+        amount: Amount,
+    },
+    DepositAndStake {
+        account: String,
+        amount: Amount,
+    },
+    Stake {
+        account: String,
+        amount: Amount,
+    },
+    StakeAll {
+        account: String,
+    },
+    Unstake {
+        account: String,
+        amount: Amount,
+    },
+    UnstakeAll {
+        account: String,
+    },
+    Withdraw {
+        account: String,
+        amount: Amount,
+    },
+    WithdrawAll {
+        account: String,
+    },
+    WaitEpochs {
+        epochs: u64,
+    },
+    FastForward {
+        blocks: u64,
+    },
+    /// Create a farm of the scenario token, funded by the owner.
+    CreateFarm {
+        amount: Amount,
+    },
+    Claim {
+        account: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        delegator: Option<String>,
+    },
+    /// Stop the last farm created by the scenario.
+    StopFarm,
+}
+
+/// State expected after a step. Only the listed fields are checked.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Expectations {
+    /// Farm `get_account` per scenario account.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, ExpectedAccount>,
+    /// Validator `get_account` of the farm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validator: Option<ExpectedAccount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_summary: Option<ExpectedPoolSummary>,
+    /// Balance of the scenario token per scenario account, or `owner`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub token_balances: BTreeMap<String, ExpectedRange>,
+    /// `get_unclaimed_reward` in the last farm created, per scenario account.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unclaimed_rewards: BTreeMap<String, ExpectedRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpectedAccount {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staked_balance: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unstaked_balance: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub can_withdraw: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpectedPoolSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_staked_balance: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_farms: Option<usize>,
+}
+
+/// Bounds of a balance that depends on timing, such as farm rewards. Both are inclusive.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpectedRange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<Amount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<Amount>,
+}
+
+/// A balance written the way `parse_near!` takes it, e.g. `"1000 N"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Amount(pub Balance);
+
+const ONE_NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (whole, fraction) = (self.0 / ONE_NEAR, self.0 % ONE_NEAR);
+
+        let s = if fraction == 0 {
+            format!("{} N", whole)
+        } else {
+            let fraction = format!("{:024}", fraction);
+            format!("{}.{} N", whole, fraction.trim_end_matches('0'))
+        };
+
+        serializer.serialize_str(&s)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+
+        near_units::near::parse(&s)
+            .map(Amount)
+            .map_err(|err| de::Error::custom(format!("invalid amount {:?}: {}", s, err)))
+    }
+}
+
+impl Scenario {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+
+        let scenario = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => serde_yaml::from_str(&content)?,
+        };
+
+        Ok(scenario)
+    }
+
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// Load every scenario file in `dir`, sorted by file name.
+pub fn load_scenarios(dir: &Path) -> anyhow::Result<Vec<Scenario>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    paths
+        .iter()
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("yaml" | "yml" | "json")
+            )
+        })
+        .map(|path| {
+            Scenario::from_file(path).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+        })
+        .collect()
+}
+
+struct ScenarioRun<'a> {
+    env: &'a TestEnv,
+    worker: &'a Worker<Sandbox>,
+    owner: &'a Account,
+    farm: StakingFarmClient,
+    validator: ValidatorPoolClient,
+    accounts: HashMap<String, Account>,
+    token: Option<FungibleTokenClient>,
+    farm_ids: Vec<u64>,
+}
+
+/// Run a scenario against the freshly initialized contracts of `env`. Scenario accounts and
+/// the token are named through `env`, so they don't collide with other tests.
+pub async fn run_scenario(env: &TestEnv, scenario: &Scenario) -> anyhow::Result<()> {
+    log!("Start scenario: {}", scenario.name);
+
+    let (staking_farm_contract, validator_contract) =
+        (&env.staking_farm_contract, &env.validator_contract);
+    let mut run = ScenarioRun {
+        env,
+        worker: &env.worker,
+        owner: &env.owner,
+        farm: StakingFarmClient::new(staking_farm_contract, &env.owner),
+        validator: ValidatorPoolClient::new(validator_contract, &env.owner),
+        accounts: HashMap::new(),
+        token: None,
+        farm_ids: vec![],
+    };
+
+    for account in &scenario.accounts {
+        let created = env.create_account(&account.name, account.balance.0).await?;
+        run.accounts.insert(account.name.clone(), created);
+    }
+
+    let mut invariants = if scenario.check_invariants {
+        Some(
+            InvariantChecker::new(staking_farm_contract, validator_contract)
+                .unowned_stake(env.farm_unowned_stake),
+        )
+    } else {
+        None
+    };
+
+    for (i, step) in scenario.steps.iter().enumerate() {
+        let context = format!("{} step #{} ({:?})", scenario.name, i, step.action);
+
+        match (run.apply(&step.action).await, &step.expect_failure) {
+            (Ok(()), None) => {}
+            (Ok(()), Some(expected)) => {
+                anyhow::bail!("{}: expected to fail with {:?}", context, expected)
+            }
+            (Err(err), Some(expected)) if err.to_string().contains(expected.as_str()) => {}
+            (Err(err), _) => anyhow::bail!("{}: {}", context, err),
+        }
+
+        if let Some(invariants) = &mut invariants {
+            invariants.check(&context).await?;
+        }
+
+        if let Some(expect) = &step.expect {
+            run.check(expect)
+                .await
+                .map_err(|err| anyhow::anyhow!("{}: {}", context, err))?;
+        }
+    }
+
+    log!("Passed ✅ scenario {}", scenario.name);
+    Ok(())
+}
+
+impl<'a> ScenarioRun<'a> {
+    fn account(&self, name: &str) -> anyhow::Result<&Account> {
+        self.accounts
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("unknown account {:?}", name))
+    }
+
+    /// A scenario account, or the owner for `owner`.
+    fn account_id(&self, name: &str) -> anyhow::Result<&AccountId> {
+        match self.accounts.get(name) {
+            Some(account) => Ok(account.id()),
+            None if name == "owner" => Ok(self.owner.id()),
+            None => anyhow::bail!("unknown account {:?}", name),
+        }
+    }
+
+    fn farm_as(&self, name: &str) -> anyhow::Result<StakingFarmClient> {
+        Ok(self.farm.with_signer(self.account(name)?))
+    }
+
+    async fn apply(&mut self, action: &Action) -> anyhow::Result<()> {
+        match action {
+            Action::Deposit { account, amount } => {
+                self.farm_as(account)?.deposit(amount.0).await?;
+            }
+            Action::DepositAndStake { account, amount } => {
+                self.farm_as(account)?.deposit_and_stake(amount.0).await?;
+            }
+            Action::Stake { account, amount } => {
+                self.farm_as(account)?.stake(amount.0).await?;
+            }
+            Action::StakeAll { account } => {
+                self.farm_as(account)?.stake_all().await?;
+            }
+            Action::Unstake { account, amount } => {
+                self.farm_as(account)?.unstake(amount.0).await?;
+            }
+            Action::UnstakeAll { account } => {
+                self.farm_as(account)?.unstake_all().await?;
+            }
+            Action::Withdraw { account, amount } => {
+                self.farm_as(account)?.withdraw(amount.0).await?;
+            }
+            Action::WithdrawAll { account } => {
+                self.farm_as(account)?.withdraw_all().await?;
+            }
+            Action::WaitEpochs { epochs } => {
+                wait_epochs(self.worker, *epochs).await?;
+            }
+            Action::FastForward { blocks } => {
+                self.worker.fast_forward(*blocks).await?;
+            }
+            Action::CreateFarm { amount } => {
+                let token = self.token().await?;
+                token.mint(self.owner.id(), amount.0).await?;
+                let created = self
+                    .farm
+                    .create_farm_and_get_id(self.worker, &FarmSpec::new(&token, amount.0))
+                    .await?;
+                self.farm_ids.push(created);
+            }
+            Action::Claim { account, delegator } => {
+                let token = self.token().await?;
+                let delegator = match delegator {
+                    Some(name) => Some(self.account(name)?.id().clone()),
+                    None => None,
+                };
+
+                self.farm_as(account)?
+                    .claim(token.id(), delegator.as_ref())
+                    .await?;
+            }
+            Action::StopFarm => {
+                let farm_id = self
+                    .farm_ids
+                    .last()
+                    .ok_or_else(|| anyhow::anyhow!("stop_farm before create_farm"))?;
+
+                self.farm.stop_farm(*farm_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The scenario's farm token, deployed and registered for everyone on first use.
+    async fn token(&mut self) -> anyhow::Result<FungibleTokenClient> {
+        if let Some(token) = &self.token {
+            return Ok(token.clone());
+        }
+
+        let token_name = self.env.account_name("token");
+        let token = deploy_fungible_token(self.worker, &token_name, self.owner).await?;
+
+        let mut account_ids = vec![self.owner.id(), self.farm.id()];
+        account_ids.extend(self.accounts.values().map(|account| account.id()));
+        token.register_accounts(&account_ids).await?;
+
+        self.farm.add_authorized_farm_token(token.id()).await?;
+
+        self.token = Some(token.clone());
+        Ok(token)
+    }
+
+    async fn check(&self, expect: &Expectations) -> anyhow::Result<()> {
+        for (name, expected) in &expect.accounts {
+            let account = self.farm.get_account(self.account(name)?.id()).await?;
+            check_account(name, expected, &account)?;
+        }
+
+        if let Some(expected) = &expect.validator {
+            let account = self.validator.get_account(self.farm.id()).await?;
+            check_account("validator", expected, &account)?;
+        }
+
+        if let Some(expected) = &expect.pool_summary {
+            let summary = self.farm.get_pool_summary().await?;
+
+            check_field(
+                "pool_summary.total_staked_balance",
+                expected.total_staked_balance.map(|a| a.0),
+                summary.total_staked_balance.0,
+            )?;
+            check_field(
+                "pool_summary.active_farms",
+                expected.active_farms,
+                summary.farms.len(),
+            )?;
+        }
+
+        if !expect.token_balances.is_empty() {
+            let token = self
+                .token
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("token_balances expected before create_farm"))?;
+
+            for (name, expected) in &expect.token_balances {
+                let balance = token.ft_balance_of(self.account_id(name)?).await?;
+                check_range(&format!("token_balances.{}", name), expected, balance)?;
+            }
+        }
+
+        if !expect.unclaimed_rewards.is_empty() {
+            let farm_id = self
+                .farm_ids
+                .last()
+                .ok_or_else(|| anyhow::anyhow!("unclaimed_rewards expected before create_farm"))?;
+
+            for (name, expected) in &expect.unclaimed_rewards {
+                let reward = self
+                    .farm
+                    .get_unclaimed_reward(self.account_id(name)?, *farm_id)
+                    .await?;
+                check_range(&format!("unclaimed_rewards.{}", name), expected, reward)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_account(
+    name: &str,
+    expected: &ExpectedAccount,
+    actual: &HumanReadableAccount,
+) -> anyhow::Result<()> {
+    check_field(
+        &format!("{}.staked_balance", name),
+        expected.staked_balance.map(|a| a.0),
+        actual.staked_balance.0,
+    )?;
+    check_field(
+        &format!("{}.unstaked_balance", name),
+        expected.unstaked_balance.map(|a| a.0),
+        actual.unstaked_balance.0,
+    )?;
+    check_field(
+        &format!("{}.can_withdraw", name),
+        expected.can_withdraw,
+        actual.can_withdraw,
+    )
+}
+
+fn check_range(field: &str, expected: &ExpectedRange, actual: Balance) -> anyhow::Result<()> {
+    if let Some(min) = expected.min {
+        if actual < min.0 {
+            anyhow::bail!("{} expected at least {:?}, got {}", field, min, actual)
+        }
+    }
+    if let Some(max) = expected.max {
+        if actual > max.0 {
+            anyhow::bail!("{} expected at most {:?}, got {}", field, max, actual)
+        }
+    }
+
+    Ok(())
+}
+
+fn check_field<T: PartialEq + std::fmt::Debug>(
+    field: &str,
+    expected: Option<T>,
+    actual: T,
+) -> anyhow::Result<()> {
+    match expected {
+        Some(expected) if expected != actual => {
+            anyhow::bail!("{} expected {:?}, got {:?}", field, expected, actual)
+        }
+        _ => Ok(()),
+    }
+}
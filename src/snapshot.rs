@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+use near_sdk::Balance;
+use workspaces::AccountId;
+
+use crate::*;
+
+const ONE_NEAR: f64 = 1_000_000_000_000_000_000_000_000.0;
+
+/// A value in a `Snapshot`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotValue {
+    Balance(Balance),
+    Bool(bool),
+    Text(String),
+}
+
+impl fmt::Display for SnapshotValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotValue::Balance(balance) => {
+                write!(f, "{} ({:.4} N)", balance, *balance as f64 / ONE_NEAR)
+            }
+            SnapshotValue::Bool(value) => write!(f, "{}", value),
+            SnapshotValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Everything a step can change, keyed like:
+///
+/// - `farm.<label>.staked_balance`, `.unstaked_balance`, `.can_withdraw`: farm `get_account`
+/// - `validator.<label>.staked_balance`, ...: validator `get_account`
+/// - `pool.total_staked_balance`, `.reward_fee_fraction`, `.next_reward_fee_fraction`,
+///   `.owner`, `.farms`: farm `get_pool_summary`
+/// - `native.<label>`: NEAR balance
+/// - `ft.<token>.<label>`: `ft_balance_of`
+///
+/// `farm` and `validator` are always included as labels of their contracts.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub values: BTreeMap<String, SnapshotValue>,
+}
+
+/// Takes snapshots of a farm and its validator for a set of accounts and tokens.
+#[derive(Clone)]
+pub struct Snapshotter {
+    worker: Worker<Sandbox>,
+    farm: StakingFarmClient,
+    validator: ValidatorPoolClient,
+    accounts: Vec<(String, AccountId)>,
+    tokens: Vec<FungibleTokenClient>,
+}
+
+impl Snapshotter {
+    pub fn new(
+        worker: &Worker<Sandbox>,
+        staking_farm_contract: &Contract,
+        validator_contract: &Contract,
+    ) -> Self {
+        let signer = staking_farm_contract.as_account();
+
+        Self {
+            worker: worker.clone(),
+            farm: StakingFarmClient::new(staking_farm_contract, signer),
+            validator: ValidatorPoolClient::new(validator_contract, signer),
+            accounts: vec![
+                ("farm".to_string(), staking_farm_contract.id().clone()),
+                ("validator".to_string(), validator_contract.id().clone()),
+            ],
+            tokens: vec![],
+        }
+    }
+
+    /// Include `account_id` under `label`.
+    pub fn account(mut self, label: &str, account_id: &AccountId) -> Self {
+        self.accounts.push((label.to_string(), account_id.clone()));
+        self
+    }
+
+    /// Include the balances of all accounts in `token`.
+    pub fn token(mut self, token: &FungibleTokenClient) -> Self {
+        self.tokens.push(token.clone());
+        self
+    }
+
+    pub async fn take(&self) -> anyhow::Result<Snapshot> {
+        let mut values = BTreeMap::new();
+        let mut insert = |key: String, value: SnapshotValue| {
+            values.insert(key, value);
+        };
+
+        for (label, account_id) in &self.accounts {
+            let accounts = vec![
+                ("farm", self.farm.get_account(account_id).await?),
+                ("validator", self.validator.get_account(account_id).await?),
+            ];
+            for (prefix, account) in accounts {
+                insert(
+                    format!("{}.{}.staked_balance", prefix, label),
+                    SnapshotValue::Balance(account.staked_balance.0),
+                );
+                insert(
+                    format!("{}.{}.unstaked_balance", prefix, label),
+                    SnapshotValue::Balance(account.unstaked_balance.0),
+                );
+                insert(
+                    format!("{}.{}.can_withdraw", prefix, label),
+                    SnapshotValue::Bool(account.can_withdraw),
+                );
+            }
+
+            let native = self.worker.view_account(account_id).await?;
+            insert(
+                format!("native.{}", label),
+                SnapshotValue::Balance(native.balance),
+            );
+
+            for token in &self.tokens {
+                insert(
+                    format!("ft.{}.{}", token.id(), label),
+                    SnapshotValue::Balance(token.ft_balance_of(account_id).await?),
+                );
+            }
+        }
+
+        let pool_summary = self.farm.get_pool_summary().await?;
+        let ratio = |ratio: &Ratio| {
+            SnapshotValue::Text(format!("{}/{}", ratio.numerator, ratio.denominator))
+        };
+        insert(
+            "pool.total_staked_balance".to_string(),
+            SnapshotValue::Balance(pool_summary.total_staked_balance.0),
+        );
+        insert(
+            "pool.reward_fee_fraction".to_string(),
+            ratio(&pool_summary.reward_fee_fraction),
+        );
+        insert(
+            "pool.next_reward_fee_fraction".to_string(),
+            ratio(&pool_summary.next_reward_fee_fraction),
+        );
+        insert(
+            "pool.owner".to_string(),
+            SnapshotValue::Text(pool_summary.owner.to_string()),
+        );
+        insert(
+            "pool.farms".to_string(),
+            SnapshotValue::Text(format!(
+                "{:?}",
+                pool_summary
+                    .farms
+                    .iter()
+                    .map(|farm| farm.farm_id)
+                    .collect::<Vec<_>>()
+            )),
+        );
+
+        Ok(Snapshot { values })
+    }
+}
+
+/// How one value changed between two snapshots.
+#[derive(Debug, Clone)]
+pub struct SnapshotChange {
+    pub key: String,
+    pub before: Option<SnapshotValue>,
+    pub after: Option<SnapshotValue>,
+}
+
+impl SnapshotChange {
+    /// `after - before` for balances, `None` for other values and changes beyond `i128`.
+    pub fn delta(&self) -> Option<i128> {
+        match (&self.before, &self.after) {
+            (Some(SnapshotValue::Balance(before)), Some(SnapshotValue::Balance(after))) => {
+                if after >= before {
+                    i128::try_from(after - before).ok()
+                } else {
+                    i128::try_from(before - after).ok().map(|delta| -delta)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SnapshotChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<SnapshotValue>| match value {
+            Some(value) => value.to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.key,
+            show(&self.before),
+            show(&self.after)
+        )?;
+        if let Some(delta) = self.delta() {
+            write!(f, " ({:+.4} N)", delta as f64 / ONE_NEAR)?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything that changed between two snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    /// Keys of both snapshots, changed or not.
+    pub keys: BTreeSet<String>,
+    pub changes: BTreeMap<String, SnapshotChange>,
+}
+
+impl Snapshot {
+    pub fn get(&self, key: &str) -> Option<&SnapshotValue> {
+        self.values.get(key)
+    }
+
+    pub fn diff(&self, later: &Snapshot) -> SnapshotDiff {
+        let keys: BTreeSet<String> = self
+            .values
+            .keys()
+            .chain(later.values.keys())
+            .cloned()
+            .collect();
+
+        let changes = keys
+            .iter()
+            .filter_map(|key| {
+                let (before, after) = (self.values.get(key), later.values.get(key));
+                if before == after {
+                    return None;
+                }
+                let change = SnapshotChange {
+                    key: key.clone(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                };
+                Some((key.clone(), change))
+            })
+            .collect();
+
+        SnapshotDiff { keys, changes }
+    }
+}
+
+impl SnapshotDiff {
+    pub fn print(&self) {
+        if self.changes.is_empty() {
+            log!("No changes");
+        }
+        for change in self.changes.values() {
+            log!("{}", change);
+        }
+    }
+
+    /// The change of a balance, 0 if it didn't change. `None` for keys in neither snapshot,
+    /// values that aren't balances and changes beyond `i128`.
+    pub fn delta(&self, key: &str) -> Option<i128> {
+        if !self.keys.contains(key) {
+            return None;
+        }
+        match self.changes.get(key) {
+            Some(change) => change.delta(),
+            None => Some(0),
+        }
+    }
+
+    /// Assert that every listed balance changed by its delta, within `tolerance`. Balances
+    /// not listed may change, e.g. native balances paying for gas. Keys in neither snapshot
+    /// fail, so a typo can't pass as an unchanged balance.
+    pub fn assert_deltas(&self, expected: &[(&str, i128)], tolerance: Balance) {
+        let mismatches: Vec<String> = expected
+            .iter()
+            .filter_map(|(key, delta)| {
+                if !self.keys.contains(*key) {
+                    return Some(format!("{}: not in either snapshot", key));
+                }
+                let actual = match self.delta(key) {
+                    Some(actual) => actual,
+                    None => return Some(format!("{}: not a balance change", key)),
+                };
+                let within_tolerance = actual
+                    .checked_sub(*delta)
+                    .map_or(false, |diff| diff.unsigned_abs() <= tolerance);
+                if within_tolerance {
+                    return None;
+                }
+                Some(format!(
+                    "{}: expected {:+}, changed by {:+}",
+                    key, delta, actual
+                ))
+            })
+            .collect();
+
+        if !mismatches.is_empty() {
+            self.print();
+        }
+        assert!(
+            mismatches.is_empty(),
+            "unexpected changes:\n  {}",
+            mismatches.join("\n  ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(values: &[(&str, Balance)]) -> Snapshot {
+        Snapshot {
+            values: values
+                .iter()
+                .map(|(key, value)| (key.to_string(), SnapshotValue::Balance(*value)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn deltas_of_known_keys() {
+        let before = snapshot(&[("farm.alice.staked_balance", 10), ("native.alice", 5)]);
+        let after = snapshot(&[("farm.alice.staked_balance", 4), ("native.alice", 5)]);
+        let diff = before.diff(&after);
+
+        assert_eq!(diff.delta("farm.alice.staked_balance"), Some(-6));
+        assert_eq!(diff.delta("native.alice"), Some(0));
+        assert_eq!(diff.delta("farm.alcie.staked_balance"), None);
+        diff.assert_deltas(&[("farm.alice.staked_balance", -6), ("native.alice", 0)], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "farm.alcie.staked_balance: not in either snapshot")]
+    fn unknown_key_fails() {
+        let before = snapshot(&[("farm.alice.staked_balance", 10)]);
+        before
+            .diff(&before)
+            .assert_deltas(&[("farm.alcie.staked_balance", 0)], 0);
+    }
+
+    #[test]
+    fn deltas_past_i128() {
+        let big = u128::MAX - 1;
+        let up = SnapshotChange {
+            key: "x".to_string(),
+            before: Some(SnapshotValue::Balance(1)),
+            after: Some(SnapshotValue::Balance(big)),
+        };
+        let down = SnapshotChange {
+            before: up.after.clone(),
+            after: up.before.clone(),
+            ..up.clone()
+        };
+        let small = SnapshotChange {
+            before: Some(SnapshotValue::Balance(big)),
+            after: Some(SnapshotValue::Balance(big - 3)),
+            ..up.clone()
+        };
+
+        assert_eq!(up.delta(), None);
+        assert_eq!(down.delta(), None);
+        assert_eq!(small.delta(), Some(-3));
+    }
+}
@@ -1,330 +1,409 @@
-use near_sdk::{json_types::U128, Balance};
+use near_sdk::{json_types::U128, serde::de::DeserializeOwned, Balance};
 use workspaces::AccountId;
 
 use crate::*;
 
-const ONE_SEC_IN_NS: u64 = 1_000_000_000;
-
-// STAKE METHODS ===========================
-// ========================================
-
-pub async fn deposit(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "deposit")
-        .deposit(amount)
-        .gas(parse_gas!("100 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::deposit");
-
-    Ok(())
+/// More farms than any test creates.
+const FARMS_PAGE_SIZE: u64 = 1000;
+
+/// Typed client for `contracts/staking_farm.wasm`.
+///
+/// Change methods return a [`ContractCall`] signed by the default signer; gas, deposit and
+/// signer can be overridden on it before awaiting. Private callbacks (`on_*`, `callback_*`,
+/// `update`) and `ft_on_transfer` are not exposed.
+#[derive(Clone)]
+pub struct StakingFarmClient {
+    pub contract: Contract,
+    pub signer: Account,
 }
 
-pub async fn deposit_and_stake(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "deposit_and_stake")
-        .deposit(amount)
-        .gas(parse_gas!("130 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::deposit_and_stake");
-
-    Ok(())
-}
-
-pub async fn stake(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "stake")
-        .args_json(json!({ "amount": U128(amount) }))
-        .gas(parse_gas!("130 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::stake");
-
-    Ok(())
-}
-
-pub async fn unstake(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "unstake")
-        .args_json(json!({ "amount": U128(amount) }))
-        .gas(parse_gas!("200 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::unstake");
-
-    Ok(())
-}
-
-pub async fn stake_all(staking_farm_contract: &Contract, user: &Account) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "stake_all")
-        .gas(parse_gas!("130 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::stake_all");
-
-    Ok(())
-}
-
-pub async fn unstake_all(staking_farm_contract: &Contract, user: &Account) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "unstake_all")
-        .gas(parse_gas!("200 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::unstake_all");
-
-    Ok(())
-}
-
-pub async fn withdraw(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "withdraw")
-        .args_json(json!({ "amount": U128(amount) }))
-        .gas(parse_gas!("130 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::withdraw");
-
-    Ok(())
-}
-
-pub async fn withdraw_all(staking_farm_contract: &Contract, user: &Account) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "withdraw_all")
-        .gas(parse_gas!("130 T") as u64)
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::withdraw_all");
-
-    Ok(())
-}
-
-pub async fn get_account_unstaked_balance(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        staking_farm_contract,
-        "get_account_unstaked_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
-
-pub async fn get_account_staked_balance(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        staking_farm_contract,
-        "get_account_staked_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
-
-pub async fn get_account_total_balance(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        staking_farm_contract,
-        "get_account_total_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
-
-pub async fn is_account_unstaked_balance_available(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<bool> {
-    let res: bool = view_call(
-        user,
-        staking_farm_contract,
-        "is_account_unstaked_balance_available",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res)
-}
-
-pub async fn get_account(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<HumanReadableAccount> {
-    let res: HumanReadableAccount = view_call(
-        user,
-        staking_farm_contract,
-        "get_account",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res)
-}
-
-pub async fn get_pool_summary(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<PoolSummary> {
-    let res: PoolSummary = view_call(user, staking_farm_contract, "get_pool_summary", json!({}))
-        .await?
-        .json()?;
-
-    Ok(res)
-}
-
-pub async fn is_contract_can_withdraw(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<bool> {
-    let res: bool = view_call(
-        user,
-        staking_farm_contract,
-        "is_contract_can_withdraw",
-        json!({}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res)
-}
-
-// FARM METHODS ===========================
-// ========================================
-
-/// Transfer the given amount of ft_contract token to the staking_farm_contract.
-pub async fn transfer_farm_token(
-    worker: &Worker<Sandbox>,
-    ft_contract: &Contract,
-    staking_farm_contract: &Contract,
-    user: &Account,
-    amount: Balance,
-) -> anyhow::Result<()> {
-    let block = worker.view_block().await?;
-    let start_date = block.timestamp() + ONE_SEC_IN_NS * 3;
-    let end_date = start_date + ONE_SEC_IN_NS * 100;
-    let msg =
-        serde_json::to_string(&json!({ "name": "Test", "start_date": format!("{}", start_date), "end_date": format!("{}", end_date) }))
-            .unwrap();
-    let res = user
-        .call(ft_contract.id(), "ft_transfer_call")
-        .deposit(1)
-        .args_json(json!({
-            "receiver_id": staking_farm_contract.id(),
-            "amount": amount,
-            "msg": msg,
-        }))
-        .transact()
-        .await?;
-    check_res(&res, "transfer_farm_token - ft_contract::ft_transfer_call");
-
-    Ok(())
-}
-
-pub async fn get_active_farms(
-    staking_farm_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<Vec<HumanReadableFarm>> {
-    let res: Vec<HumanReadableFarm> =
-        view_call(user, staking_farm_contract, "get_active_farms", json!({}))
+impl StakingFarmClient {
+    pub fn new(contract: &Contract, signer: &Account) -> Self {
+        Self {
+            contract: contract.clone(),
+            signer: signer.clone(),
+        }
+    }
+
+    /// Same contract, different default signer.
+    pub fn with_signer(&self, signer: &Account) -> Self {
+        Self::new(&self.contract, signer)
+    }
+
+    pub fn id(&self) -> &AccountId {
+        self.contract.id()
+    }
+
+    fn call(&self, method: &str) -> ContractCall {
+        ContractCall::new(&self.signer, self.contract.id(), method).contract_name("staking_farm")
+    }
+
+    async fn view<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        args_json: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let res: T = view_call(&self.signer, &self.contract, method, args_json)
             .await?
             .json()?;
 
-    Ok(res)
-}
-
-pub async fn get_unclaimed_reward(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    farm_id: u64,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        staking_farm_contract,
-        "get_unclaimed_reward",
-        json!({"account_id": user.id(), "farm_id": farm_id}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
-
-pub async fn claim(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    token_id: AccountId,
-    delegator_id: Option<AccountId>,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "claim")
-        .deposit(1)
-        .gas(parse_gas!("100 T") as u64)
-        .args_json(json!({
-            "token_id": token_id,
-            "delegator_id": delegator_id,
+        Ok(res)
+    }
+
+    // INIT METHODS ===========================
+    // ========================================
+
+    pub fn init(
+        &self,
+        owner_id: &AccountId,
+        validator_id: &AccountId,
+        reward_fee_fraction: Ratio,
+    ) -> ContractCall {
+        self.call("new").args_json(json!({
+            "owner_id": owner_id,
+            "validator_id": validator_id,
+            "reward_fee_fraction": reward_fee_fraction,
         }))
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::claim");
-
-    Ok(())
+    }
+
+    // STAKE METHODS ===========================
+    // ========================================
+
+    pub fn deposit(&self, amount: Balance) -> ContractCall {
+        self.call("deposit")
+            .deposit(amount)
+            .gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn deposit_and_stake(&self, amount: Balance) -> ContractCall {
+        self.call("deposit_and_stake")
+            .deposit(amount)
+            .gas(parse_gas!("130 T") as u64)
+    }
+
+    pub fn stake(&self, amount: Balance) -> ContractCall {
+        self.call("stake")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("130 T") as u64)
+    }
+
+    pub fn stake_all(&self) -> ContractCall {
+        self.call("stake_all").gas(parse_gas!("130 T") as u64)
+    }
+
+    pub fn unstake(&self, amount: Balance) -> ContractCall {
+        self.call("unstake")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("200 T") as u64)
+    }
+
+    pub fn unstake_all(&self) -> ContractCall {
+        self.call("unstake_all").gas(parse_gas!("200 T") as u64)
+    }
+
+    pub fn withdraw(&self, amount: Balance) -> ContractCall {
+        self.call("withdraw")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("130 T") as u64)
+    }
+
+    pub fn withdraw_all(&self) -> ContractCall {
+        self.call("withdraw_all").gas(parse_gas!("130 T") as u64)
+    }
+
+    pub fn ping(&self) -> ContractCall {
+        self.call("ping").gas(parse_gas!("100 T") as u64)
+    }
+
+    pub async fn get_account_unstaked_balance(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_unstaked_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn get_account_staked_balance(&self, account_id: &AccountId) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_staked_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn get_account_total_balance(&self, account_id: &AccountId) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_total_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn is_account_unstaked_balance_available(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<bool> {
+        self.view(
+            "is_account_unstaked_balance_available",
+            json!({ "account_id": account_id }),
+        )
+        .await
+    }
+
+    pub async fn get_account(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<HumanReadableAccount> {
+        self.view("get_account", json!({ "account_id": account_id }))
+            .await
+    }
+
+    pub async fn get_accounts(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> anyhow::Result<Vec<HumanReadableAccount>> {
+        self.view(
+            "get_accounts",
+            json!({ "from_index": from_index, "limit": limit }),
+        )
+        .await
+    }
+
+    pub async fn get_number_of_accounts(&self) -> anyhow::Result<u64> {
+        self.view("get_number_of_accounts", json!({})).await
+    }
+
+    pub async fn get_total_staked_balance(&self) -> anyhow::Result<u128> {
+        let res: U128 = self.view("get_total_staked_balance", json!({})).await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn get_pool_summary(&self) -> anyhow::Result<PoolSummary> {
+        self.view("get_pool_summary", json!({})).await
+    }
+
+    pub async fn is_contract_can_withdraw(&self) -> anyhow::Result<bool> {
+        self.view("is_contract_can_withdraw", json!({})).await
+    }
+
+    /// Native balance of the contract plus what it has locked. Returned as a JSON number,
+    /// not a `U128` string.
+    pub async fn internal_get_contract_total_balance(&self) -> anyhow::Result<Balance> {
+        self.view("internal_get_contract_total_balance", json!({}))
+            .await
+    }
+
+    // OWNER METHODS ===========================
+    // ========================================
+
+    pub fn set_owner_id(&self, owner_id: &AccountId) -> ContractCall {
+        self.call("set_owner_id")
+            .args_json(json!({ "owner_id": owner_id }))
+            .gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn update_reward_fee_fraction(&self, reward_fee_fraction: Ratio) -> ContractCall {
+        self.call("update_reward_fee_fraction")
+            .args_json(json!({ "reward_fee_fraction": reward_fee_fraction }))
+            .gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn pause_staking(&self) -> ContractCall {
+        self.call("pause_staking").gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn resume_staking(&self) -> ContractCall {
+        self.call("resume_staking").gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn add_authorized_user(&self, account_id: &AccountId) -> ContractCall {
+        self.call("add_authorized_user")
+            .args_json(json!({ "account_id": account_id }))
+    }
+
+    pub fn remove_authorized_user(&self, account_id: &AccountId) -> ContractCall {
+        self.call("remove_authorized_user")
+            .args_json(json!({ "account_id": account_id }))
+    }
+
+    pub fn add_authorized_farm_token(&self, token_id: &AccountId) -> ContractCall {
+        self.call("add_authorized_farm_token")
+            .args_json(json!({ "token_id": token_id }))
+    }
+
+    pub fn remove_authorized_farm_token(&self, token_id: &AccountId) -> ContractCall {
+        self.call("remove_authorized_farm_token")
+            .args_json(json!({ "token_id": token_id }))
+    }
+
+    /// Replace the contract with the code the factory stores under `code_hash`, then call
+    /// `migrate`. The hash is passed on to the factory's `get_code` as is.
+    pub fn upgrade(&self, code_hash: &[u8]) -> ContractCall {
+        self.call("upgrade")
+            .args(code_hash.to_vec())
+            .gas(parse_gas!("300 T") as u64)
+    }
+
+    /// Re-initialize the state after new code is deployed. Only the contract itself may call
+    /// it, `upgrade` does right after deploying.
+    pub fn migrate(&self) -> ContractCall {
+        self.call("migrate").gas(parse_gas!("100 T") as u64)
+    }
+
+    pub async fn get_version(&self) -> anyhow::Result<String> {
+        self.view("get_version", json!({})).await
+    }
+
+    pub async fn get_owner_id(&self) -> anyhow::Result<AccountId> {
+        self.view("get_owner_id", json!({})).await
+    }
+
+    pub async fn get_validator_id(&self) -> anyhow::Result<AccountId> {
+        self.view("get_validator_id", json!({})).await
+    }
+
+    pub async fn get_factory_id(&self) -> anyhow::Result<AccountId> {
+        self.view("get_factory_id", json!({})).await
+    }
+
+    pub async fn get_reward_fee_fraction(&self) -> anyhow::Result<Ratio> {
+        self.view("get_reward_fee_fraction", json!({})).await
+    }
+
+    pub async fn get_authorized_users(&self) -> anyhow::Result<Vec<AccountId>> {
+        self.view("get_authorized_users", json!({})).await
+    }
+
+    pub async fn get_authorized_farm_tokens(&self) -> anyhow::Result<Vec<AccountId>> {
+        self.view("get_authorized_farm_tokens", json!({})).await
+    }
+
+    // FARM METHODS ===========================
+    // ========================================
+
+    /// Transfer the given amount of `ft` tokens from the signer to the farm, creating a new
+    /// farm with the default `FarmSpec`.
+    pub async fn transfer_farm_token(
+        &self,
+        worker: &Worker<Sandbox>,
+        ft: &FungibleTokenClient,
+        amount: Balance,
+    ) -> Result<CallOutcome, CallError> {
+        self.create_farm(worker, &FarmSpec::new(ft, amount)).await
+    }
+
+    /// Create the farm described by `spec`, signed by the client's signer. Offsets in `spec`
+    /// are from the latest block.
+    pub async fn create_farm(
+        &self,
+        worker: &Worker<Sandbox>,
+        spec: &FarmSpec,
+    ) -> Result<CallOutcome, CallError> {
+        let block = worker
+            .view_block()
+            .await
+            .map_err(|err| CallError::rpc("create_farm", err))?;
+        let msg = serde_json::to_string(&spec.msg(block.timestamp())).unwrap();
+
+        spec.token
+            .with_signer(&self.signer)
+            .ft_transfer_call(self.contract.id(), spec.amount, &msg)
+            .await
+    }
+
+    /// Create the farm described by `spec` and return its id, found by comparing the active
+    /// farms before and after. Farms that are not active once created, e.g. ones that already
+    /// ended, are looked up among all farms instead.
+    pub async fn create_farm_and_get_id(
+        &self,
+        worker: &Worker<Sandbox>,
+        spec: &FarmSpec,
+    ) -> anyhow::Result<u64> {
+        let active_before = farm_ids(&self.get_active_farms().await?);
+        let all_before = farm_ids(&self.get_farms(0, FARMS_PAGE_SIZE).await?);
+
+        self.create_farm(worker, spec).await?;
+
+        let new_farm = |before: &[u64], after: Vec<u64>| {
+            after.into_iter().find(|farm_id| !before.contains(farm_id))
+        };
+
+        if let Some(farm_id) = new_farm(&active_before, farm_ids(&self.get_active_farms().await?)) {
+            return Ok(farm_id);
+        }
+        new_farm(
+            &all_before,
+            farm_ids(&self.get_farms(0, FARMS_PAGE_SIZE).await?),
+        )
+        .ok_or_else(|| anyhow::anyhow!("no new farm found after creating {:?}", spec.name))
+    }
+
+    pub async fn get_active_farms(&self) -> anyhow::Result<Vec<HumanReadableFarm>> {
+        self.view("get_active_farms", json!({})).await
+    }
+
+    pub async fn get_farms(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> anyhow::Result<Vec<HumanReadableFarm>> {
+        self.view(
+            "get_farms",
+            json!({ "from_index": from_index, "limit": limit }),
+        )
+        .await
+    }
+
+    pub async fn get_farm(&self, farm_id: u64) -> anyhow::Result<HumanReadableFarm> {
+        self.view("get_farm", json!({ "farm_id": farm_id })).await
+    }
+
+    pub async fn get_unclaimed_reward(
+        &self,
+        account_id: &AccountId,
+        farm_id: u64,
+    ) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_unclaimed_reward",
+                json!({ "account_id": account_id, "farm_id": farm_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub fn claim(&self, token_id: &AccountId, delegator_id: Option<&AccountId>) -> ContractCall {
+        self.call("claim")
+            .deposit(1)
+            .gas(parse_gas!("100 T") as u64)
+            .args_json(json!({
+                "token_id": token_id,
+                "delegator_id": delegator_id,
+            }))
+    }
+
+    pub fn stop_farm(&self, farm_id: u64) -> ContractCall {
+        self.call("stop_farm")
+            .gas(parse_gas!("100 T") as u64)
+            .args_json(json!({ "farm_id": farm_id }))
+    }
 }
 
-pub async fn stop_farm(
-    staking_farm_contract: &Contract,
-    user: &Account,
-    farm_id: u64,
-) -> anyhow::Result<()> {
-    let res = user
-        .call(staking_farm_contract.id(), "stop_farm")
-        .deposit(1)
-        .gas(parse_gas!("100 T") as u64)
-        .args_json(json!({
-            "farm_id": farm_id,
-        }))
-        .transact()
-        .await?;
-    check_res(&res, "staking_farm_contract::stop_farm");
-
-    Ok(())
+fn farm_ids(farms: &[HumanReadableFarm]) -> Vec<u64> {
+    farms.iter().map(|farm| farm.farm_id).collect()
 }
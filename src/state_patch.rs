@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::Balance;
+use workspaces::AccountId;
+
+use crate::*;
+
+/// The stake of an account in the farm or the validator, as the contracts store it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StakeState {
+    pub unstaked: Balance,
+    /// Priced at the pool's current share price.
+    pub stake_shares: Balance,
+    /// First epoch `unstaked` can be withdrawn in. 0 unlocks it right away.
+    pub unstaked_available_epoch_height: u64,
+}
+
+impl StakeState {
+    /// `amount` unstaked and withdrawable.
+    pub fn unlocked(amount: Balance) -> Self {
+        Self {
+            unstaked: amount,
+            ..Self::default()
+        }
+    }
+
+    /// `amount` unstaked and locked until `epoch_height`.
+    pub fn locked(amount: Balance, epoch_height: u64) -> Self {
+        Self {
+            unstaked: amount,
+            unstaked_available_epoch_height: epoch_height,
+            ..Self::default()
+        }
+    }
+
+    /// `stake_shares` staked and nothing unstaked.
+    pub fn staked(stake_shares: Balance) -> Self {
+        Self {
+            stake_shares,
+            ..Self::default()
+        }
+    }
+}
+
+/// Give `account_id` the stake `state` in the validator by writing its storage with
+/// `patch_state`, instead of replaying the calls that lead there.
+///
+/// Patches only add balance: the account can't end up with less unstaked balance or fewer
+/// shares than it has. What is added is transferred from `funder` into the validator and
+/// added to its totals, so the validator stays solvent and takes none of it as rewards.
+pub async fn patch_validator_account(
+    worker: &Worker<Sandbox>,
+    funder: &Account,
+    validator_contract: &Contract,
+    account_id: &AccountId,
+    state: StakeState,
+) -> anyhow::Result<()> {
+    patch_validator(
+        worker,
+        funder,
+        validator_contract,
+        account_id,
+        |_, account| {
+            check_only_adds(account_id, account.unstaked, account.stake_shares, &state)?;
+            Ok(ValidatorAccount {
+                unstaked: state.unstaked,
+                stake_shares: state.stake_shares,
+                unstaked_available_epoch_height: state.unstaked_available_epoch_height,
+            })
+        },
+    )
+    .await
+}
+
+/// The stake of `account_id` in the validator as stored, the default if it has none.
+pub async fn read_validator_stake(
+    validator_contract: &Contract,
+    account_id: &AccountId,
+) -> anyhow::Result<StakeState> {
+    let patch = StatePatch::read(validator_contract).await?;
+    let validator: ValidatorState = patch.state()?;
+
+    Ok(patch
+        .map_get::<ValidatorAccount>(&validator.accounts, account_id)?
+        .map(|account| StakeState {
+            unstaked: account.unstaked,
+            stake_shares: account.stake_shares,
+            unstaked_available_epoch_height: account.unstaked_available_epoch_height,
+        })
+        .unwrap_or_default())
+}
+
+/// Give `account_id` the stake `state` in the farm, the way `patch_validator_account` does
+/// for the validator. The farm's own account in the validator gets what is added, so the
+/// farm's books keep agreeing with the validator. New accounts start at the farms' current
+/// reward per share, without past rewards.
+///
+/// Relies on `FarmContractState` matching the deployed farm; fails if it doesn't decode.
+pub async fn patch_farm_account(
+    worker: &Worker<Sandbox>,
+    funder: &Account,
+    staking_farm_contract: &Contract,
+    validator_contract: &Contract,
+    account_id: &AccountId,
+    state: StakeState,
+) -> anyhow::Result<()> {
+    log!("Patching farm account {}: {:?}", account_id, state);
+
+    let mut patch = StatePatch::read(staking_farm_contract).await?;
+    let mut farm: FarmContractState = patch.state()?;
+
+    let old = match patch.map_get::<FarmAccount>(&farm.accounts, account_id)? {
+        Some(account) => account,
+        None => FarmAccount {
+            unstaked: 0,
+            stake_shares: 0,
+            unstaked_available_epoch_height: 0,
+            last_farm_reward_per_share: patch.reward_per_share(&farm)?,
+            amounts: Default::default(),
+            is_burn_account: false,
+        },
+    };
+    check_only_adds(account_id, old.unstaked, old.stake_shares, &state)?;
+
+    let added_unstaked = state.unstaked - old.unstaked;
+    let added_shares = state.stake_shares - old.stake_shares;
+    let added_staked = staked_amount(
+        added_shares,
+        farm.total_staked_balance,
+        farm.total_stake_shares,
+    );
+
+    // the validator holds the farm's stake, so it gets the new balance first
+    patch_validator(
+        worker,
+        funder,
+        validator_contract,
+        staking_farm_contract.id(),
+        |validator, account| {
+            let shares = if validator.total_staked_balance == 0 {
+                added_staked
+            } else {
+                mul_div(
+                    added_staked,
+                    validator.total_stake_shares,
+                    validator.total_staked_balance,
+                )
+            };
+            let unstaked_available_epoch_height = if account.unstaked == 0 {
+                state.unstaked_available_epoch_height
+            } else {
+                std::cmp::max(
+                    account.unstaked_available_epoch_height,
+                    state.unstaked_available_epoch_height,
+                )
+            };
+
+            Ok(ValidatorAccount {
+                unstaked: account.unstaked + added_unstaked,
+                stake_shares: account.stake_shares + shares,
+                unstaked_available_epoch_height,
+            })
+        },
+    )
+    .await?;
+
+    farm.total_staked_balance += added_staked;
+    farm.total_stake_shares += added_shares;
+    farm.last_total_balance += added_staked + added_unstaked;
+
+    let account = FarmAccount {
+        unstaked: state.unstaked,
+        stake_shares: state.stake_shares,
+        unstaked_available_epoch_height: state.unstaked_available_epoch_height,
+        ..old
+    };
+    patch.map_insert(&mut farm.accounts, account_id, &account)?;
+    patch.set(STATE_KEY, &farm)?;
+    patch.apply(worker, staking_farm_contract.id()).await
+}
+
+/// Replace the validator account of `account_id` with what `update` makes of it, given the
+/// validator's state and the current account, and fund and book the difference.
+async fn patch_validator(
+    worker: &Worker<Sandbox>,
+    funder: &Account,
+    validator_contract: &Contract,
+    account_id: &AccountId,
+    update: impl FnOnce(&ValidatorState, &ValidatorAccount) -> anyhow::Result<ValidatorAccount>,
+) -> anyhow::Result<()> {
+    let mut patch = StatePatch::read(validator_contract).await?;
+    let mut validator: ValidatorState = patch.state()?;
+
+    let old = patch
+        .map_get::<ValidatorAccount>(&validator.accounts, account_id)?
+        .unwrap_or(ValidatorAccount {
+            unstaked: 0,
+            stake_shares: 0,
+            unstaked_available_epoch_height: 0,
+        });
+    let account = update(&validator, &old)?;
+    log!("Patching validator account {}: {:?}", account_id, account);
+
+    let added_unstaked = account.unstaked - old.unstaked;
+    let added_shares = account.stake_shares - old.stake_shares;
+    let added_staked = staked_amount(
+        added_shares,
+        validator.total_staked_balance,
+        validator.total_stake_shares,
+    );
+    let added = added_unstaked + added_staked;
+
+    // Funded before the totals grow: a ping in a new epoch in between takes the transfer as
+    // rewards, while the other way around the validator would find its balance short.
+    if added > 0 {
+        funder
+            .transfer_near(validator_contract.id(), added)
+            .await?
+            .into_result()?;
+    }
+
+    validator.total_staked_balance += added_staked;
+    validator.total_stake_shares += added_shares;
+    validator.last_total_balance += added;
+
+    patch.map_insert(&mut validator.accounts, account_id, &account)?;
+    patch.set(STATE_KEY, &validator)?;
+    patch.apply(worker, validator_contract.id()).await
+}
+
+fn check_only_adds(
+    account_id: &AccountId,
+    unstaked: Balance,
+    stake_shares: Balance,
+    state: &StakeState,
+) -> anyhow::Result<()> {
+    if state.unstaked < unstaked || state.stake_shares < stake_shares {
+        anyhow::bail!(
+            "patching {} would remove balance: has {} unstaked and {} shares, patch has {} and {}",
+            account_id,
+            unstaked,
+            stake_shares,
+            state.unstaked,
+            state.stake_shares
+        );
+    }
+
+    Ok(())
+}
+
+/// What `stake_shares` cost at the pool's share price, rounded up like the contracts do.
+fn staked_amount(
+    stake_shares: Balance,
+    total_staked_balance: Balance,
+    total_stake_shares: Balance,
+) -> Balance {
+    if total_stake_shares == 0 {
+        stake_shares
+    } else {
+        mul_div_ceil(stake_shares, total_staked_balance, total_stake_shares)
+    }
+}
+
+/// Raw storage of a contract, with the writes to patch it with.
+struct StatePatch {
+    raw: RawState,
+    writes: RawState,
+}
+
+impl StatePatch {
+    async fn read(contract: &Contract) -> anyhow::Result<Self> {
+        Ok(Self {
+            raw: read_state(contract).await?,
+            writes: RawState::new(),
+        })
+    }
+
+    /// The contract struct.
+    fn state<T: BorshDeserialize>(&self) -> anyhow::Result<T> {
+        self.get(STATE_KEY)?
+            .ok_or_else(|| anyhow::anyhow!("no {} in storage to patch", std::any::type_name::<T>()))
+    }
+
+    fn get<T: BorshDeserialize>(&self, key: &[u8]) -> anyhow::Result<Option<T>> {
+        match self.writes.get(key).or_else(|| self.raw.get(key)) {
+            Some(value) => T::try_from_slice(value).map(Some).map_err(|err| {
+                anyhow::anyhow!(
+                    "storage doesn't decode as {}: {}",
+                    std::any::type_name::<T>(),
+                    err
+                )
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn set<T: BorshSerialize>(&mut self, key: &[u8], value: &T) -> anyhow::Result<()> {
+        self.writes.insert(key.to_vec(), value.try_to_vec()?);
+        Ok(())
+    }
+
+    fn map_get<V: BorshDeserialize>(
+        &self,
+        map: &RawUnorderedMap,
+        account_id: &AccountId,
+    ) -> anyhow::Result<Option<V>> {
+        let key = account_id.to_string().try_to_vec()?;
+        match self.get::<u64>(&[map.key_index_prefix.as_slice(), &key].concat())? {
+            Some(index) => self.get(&map.values.element_key(index)),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert or replace the value of `account_id`. New keys are appended, growing the
+    /// lengths in `map`, so the contract struct holding it has to be written back too.
+    fn map_insert<V: BorshSerialize>(
+        &mut self,
+        map: &mut RawUnorderedMap,
+        account_id: &AccountId,
+        value: &V,
+    ) -> anyhow::Result<()> {
+        let key = account_id.to_string().try_to_vec()?;
+        let index_key = [map.key_index_prefix.as_slice(), &key].concat();
+
+        let index = match self.get::<u64>(&index_key)? {
+            Some(index) => index,
+            None => {
+                let index = map.keys.len;
+                self.writes.insert(map.keys.element_key(index), key);
+                self.set(&index_key, &index)?;
+                map.keys.len += 1;
+                map.values.len += 1;
+                index
+            }
+        };
+
+        self.set(&map.values.element_key(index), value)
+    }
+
+    /// The current reward per share of every active farm.
+    fn reward_per_share(
+        &self,
+        farm: &FarmContractState,
+    ) -> anyhow::Result<BTreeMap<u64, [u64; 4]>> {
+        let mut reward_per_share = BTreeMap::new();
+        for farm_id in &farm.active_farms {
+            if let Some(raw_farm) = self.get::<RawFarm>(&farm.farms.element_key(*farm_id))? {
+                reward_per_share.insert(*farm_id, raw_farm.last_distribution.reward_per_share);
+            }
+        }
+
+        Ok(reward_per_share)
+    }
+
+    async fn apply(self, worker: &Worker<Sandbox>, contract_id: &AccountId) -> anyhow::Result<()> {
+        for (key, value) in &self.writes {
+            worker.patch_state(contract_id, key, value).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+
+use crate::*;
+
+/// Raw contract storage, key to value.
+pub type RawState = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Key of the contract struct itself.
+pub(crate) const STATE_KEY: &[u8] = b"STATE";
+
+/// Which Borsh layout to decode a contract's storage with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageLayout {
+    /// `staking_farm.wasm`: the contract struct plus the raw `OWNER`, `FACTORY` and `VERSION`
+    /// keys.
+    StakingFarm,
+    /// `staking_pool.wasm`, the core staking pool.
+    Validator,
+}
+
+// BORSH LAYOUTS ===========================
+// ========================================
+
+/// `near_sdk::collections::Vector`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawVector {
+    pub len: u64,
+    pub prefix: Vec<u8>,
+}
+
+impl RawVector {
+    pub(crate) fn element_key(&self, index: u64) -> Vec<u8> {
+        [self.prefix.as_slice(), &index.to_le_bytes()].concat()
+    }
+}
+
+/// `near_sdk::collections::UnorderedMap`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawUnorderedMap {
+    pub key_index_prefix: Vec<u8>,
+    pub keys: RawVector,
+    pub values: RawVector,
+}
+
+/// `near_sdk::collections::UnorderedSet`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawUnorderedSet {
+    pub element_index_prefix: Vec<u8>,
+    pub elements: RawVector,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawRatio {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+/// `staking_pool::StakingContract`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ValidatorState {
+    pub owner_id: String,
+    pub stake_public_key: Vec<u8>,
+    pub last_epoch_height: u64,
+    pub last_total_balance: u128,
+    pub total_staked_balance: u128,
+    pub total_stake_shares: u128,
+    pub reward_fee_fraction: RawRatio,
+    pub accounts: RawUnorderedMap,
+    pub paused: bool,
+}
+
+/// `staking_pool::Account`, the value of `ValidatorState::accounts`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct ValidatorAccount {
+    pub unstaked: u128,
+    pub stake_shares: u128,
+    pub unstaked_available_epoch_height: u64,
+}
+
+/// The farm's reward fee, which changes at the next epoch.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawUpdatableRewardFee {
+    pub reward_fee_fraction: RawRatio,
+    pub next_reward_fee_fraction: RawRatio,
+}
+
+/// `StakingContract` of `pool/src/lib.rs` of the farm.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct FarmContractState {
+    pub validator_id: String,
+    pub last_epoch_height: u64,
+    pub last_total_balance: u128,
+    pub total_staked_balance: u128,
+    pub total_stake_shares: u128,
+    pub total_burn_shares: u128,
+    pub reward_fee_fraction: RawUpdatableRewardFee,
+    pub accounts: RawUnorderedMap,
+    pub paused: bool,
+    pub authorized_users: RawUnorderedSet,
+    pub authorized_farm_tokens: RawUnorderedSet,
+    pub active_farms: Vec<u64>,
+    pub farms: RawVector,
+}
+
+/// A farm account, the value of `FarmContractState::accounts`. Reward per share is a U256 as
+/// four little-endian u64 words.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct FarmAccount {
+    pub unstaked: u128,
+    pub stake_shares: u128,
+    pub unstaked_available_epoch_height: u64,
+    pub last_farm_reward_per_share: BTreeMap<u64, [u64; 4]>,
+    pub amounts: BTreeMap<String, u128>,
+    pub is_burn_account: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawRewardDistribution {
+    pub undistributed: u128,
+    pub unclaimed: u128,
+    pub reward_per_share: [u64; 4],
+    pub reward_round: u64,
+}
+
+/// An element of `FarmContractState::farms`.
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+pub struct RawFarm {
+    pub name: String,
+    pub token_id: String,
+    pub amount: u128,
+    pub start_date: u64,
+    pub end_date: u64,
+    pub last_distribution: RawRewardDistribution,
+}
+
+// DUMP ===========================
+// ========================================
+
+/// Contract storage with every key it could decode given a readable name (`STATE`,
+/// `accounts[alice.test.near]`, `farms[0]`, ...) and its value pretty-printed. Keys it
+/// couldn't decode are kept as `raw:<key>` with the value in hex.
+#[derive(Debug, Clone, Default)]
+pub struct StorageDump {
+    pub entries: BTreeMap<String, String>,
+    /// Values that didn't match their layout.
+    pub errors: Vec<String>,
+}
+
+/// A key whose value differs between two dumps. `None` if the key is missing.
+#[derive(Debug, Clone)]
+pub struct StorageChange {
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Read the raw storage of `contract` through the sandbox's `view_state`.
+pub async fn read_state(contract: &Contract) -> anyhow::Result<RawState> {
+    Ok(contract.view_state().await?.into_iter().collect())
+}
+
+/// Decode the contract struct of a farm, e.g. for fields no view method returns such as
+/// `paused`.
+pub async fn read_farm_state(
+    staking_farm_contract: &Contract,
+) -> anyhow::Result<FarmContractState> {
+    let raw = read_state(staking_farm_contract).await?;
+    let value = raw
+        .get(STATE_KEY)
+        .ok_or_else(|| anyhow::anyhow!("{} has no STATE", staking_farm_contract.id()))?;
+
+    Ok(FarmContractState::try_from_slice(value)?)
+}
+
+/// Read and decode the storage of `contract`.
+pub async fn dump_storage(
+    contract: &Contract,
+    layout: StorageLayout,
+) -> anyhow::Result<StorageDump> {
+    Ok(StorageDump::decode(&read_state(contract).await?, layout))
+}
+
+impl StorageDump {
+    pub fn decode(raw: &RawState, layout: StorageLayout) -> Self {
+        let mut decoder = Decoder {
+            raw,
+            decoded: BTreeSet::new(),
+            dump: StorageDump::default(),
+        };
+
+        match layout {
+            StorageLayout::Validator => {
+                if let Some(state) = decoder.decode::<ValidatorState>("STATE", STATE_KEY) {
+                    decoder.decode_map::<ValidatorAccount>("accounts", &state.accounts);
+                }
+            }
+            StorageLayout::StakingFarm => {
+                for key in ["OWNER", "FACTORY", "VERSION"] {
+                    decoder.decode_str(key);
+                }
+                if let Some(state) = decoder.decode::<FarmContractState>("STATE", STATE_KEY) {
+                    decoder.decode_map::<FarmAccount>("accounts", &state.accounts);
+                    decoder.decode_set("authorized_users", &state.authorized_users);
+                    decoder.decode_set("authorized_farm_tokens", &state.authorized_farm_tokens);
+                    decoder.decode_vector::<RawFarm>("farms", &state.farms);
+                }
+            }
+        }
+
+        for (key, value) in raw {
+            if !decoder.decoded.contains(key) {
+                decoder
+                    .dump
+                    .entries
+                    .insert(format!("raw:{}", display_bytes(key)), hex(value));
+            }
+        }
+
+        decoder.dump
+    }
+
+    pub fn print(&self) {
+        for (key, value) in &self.entries {
+            log!("{} = {}", key, value);
+        }
+        for error in &self.errors {
+            log!("DECODE ERROR {}", error);
+        }
+    }
+
+    /// Keys added, removed or changed in `later`.
+    pub fn diff(&self, later: &StorageDump) -> Vec<StorageChange> {
+        let keys: BTreeSet<&String> = self.entries.keys().chain(later.entries.keys()).collect();
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let (before, after) = (self.entries.get(key), later.entries.get(key));
+                if before == after {
+                    return None;
+                }
+                Some(StorageChange {
+                    key: key.clone(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Print changes as `- before` / `+ after` lines under each key.
+pub fn print_storage_diff(changes: &[StorageChange]) {
+    for change in changes {
+        log!("~ {}", change.key);
+        if let Some(before) = &change.before {
+            for line in before.lines() {
+                log!("  - {}", line);
+            }
+        }
+        if let Some(after) = &change.after {
+            for line in after.lines() {
+                log!("  + {}", line);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    raw: &'a RawState,
+    /// Raw keys accounted for by decoded entries.
+    decoded: BTreeSet<Vec<u8>>,
+    dump: StorageDump,
+}
+
+impl<'a> Decoder<'a> {
+    fn decode<T: BorshDeserialize + Debug>(&mut self, name: &str, key: &[u8]) -> Option<T> {
+        let value = self.raw.get(key)?;
+
+        match T::try_from_slice(value) {
+            Ok(decoded) => {
+                self.dump
+                    .entries
+                    .insert(name.to_string(), format!("{:#?}", decoded));
+                self.decoded.insert(key.to_vec());
+                Some(decoded)
+            }
+            Err(err) => {
+                self.dump.errors.push(format!(
+                    "{} as {}: {}",
+                    name,
+                    std::any::type_name::<T>(),
+                    err
+                ));
+                None
+            }
+        }
+    }
+
+    /// A key holding a plain UTF-8 string.
+    fn decode_str(&mut self, key: &str) {
+        if let Some(value) = self.raw.get(key.as_bytes()) {
+            self.dump
+                .entries
+                .insert(key.to_string(), String::from_utf8_lossy(value).into_owned());
+            self.decoded.insert(key.as_bytes().to_vec());
+        }
+    }
+
+    fn decode_vector<T: BorshDeserialize + Debug>(&mut self, name: &str, vector: &RawVector) {
+        for index in 0..vector.len {
+            self.decode::<T>(&format!("{}[{}]", name, index), &vector.element_key(index));
+        }
+    }
+
+    /// Map entries keyed by account id, with the key index entries marked as decoded.
+    fn decode_map<V: BorshDeserialize + Debug>(&mut self, name: &str, map: &RawUnorderedMap) {
+        for index in 0..map.keys.len {
+            let key_key = map.keys.element_key(index);
+            let key = match self.raw.get(&key_key) {
+                Some(key) => key.clone(),
+                None => continue,
+            };
+            let account_id = match String::try_from_slice(&key) {
+                Ok(account_id) => account_id,
+                Err(_) => continue,
+            };
+
+            self.decoded.insert(key_key);
+            self.decoded
+                .insert([map.key_index_prefix.as_slice(), &key].concat());
+            self.decode::<V>(
+                &format!("{}[{}]", name, account_id),
+                &map.values.element_key(index),
+            );
+        }
+    }
+
+    /// Set elements, listed under one entry.
+    fn decode_set(&mut self, name: &str, set: &RawUnorderedSet) {
+        let mut elements = vec![];
+        for index in 0..set.elements.len {
+            let element_key = set.elements.element_key(index);
+            if let Some(element) = self.raw.get(&element_key) {
+                if let Ok(account_id) = String::try_from_slice(element) {
+                    self.decoded.insert(element_key);
+                    self.decoded
+                        .insert([set.element_index_prefix.as_slice(), element].concat());
+                    elements.push(account_id);
+                }
+            }
+        }
+
+        self.dump
+            .entries
+            .insert(name.to_string(), format!("{:?}", elements));
+    }
+}
+
+/// The bytes as a string if they are printable ASCII, in hex otherwise.
+fn display_bytes(bytes: &[u8]) -> String {
+    if bytes.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        hex(bytes)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,1075 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::*;
+
+/// Every test of the harness, in the order they run by default, followed by the access control
+/// matrix and the scenarios found in `SCENARIOS_DIR`.
+pub fn all_tests() -> anyhow::Result<Vec<TestCase>> {
+    let mut tests = vec![
+        TestCase::new("deposit_stake_unstake", |env| async move {
+            test_deposit_stake_unstake(
+                &env.worker,
+                &env.alice,
+                &env.staking_farm_contract,
+                &env.validator_contract,
+                env.farm_unowned_stake,
+            )
+            .await
+        })
+        .tags(&["staking"]),
+        TestCase::new("error_paths", |env| async move {
+            test_error_paths(&env.alice, &env.staking_farm_contract).await
+        })
+        .tags(&["staking", "errors"]),
+        TestCase::new("withdraw", |env| async move {
+            test_withdraw(
+                &env.worker,
+                &env.alice,
+                &env.staking_farm_contract,
+                &env.validator_contract,
+                env.farm_unowned_stake,
+            )
+            .await
+        })
+        .tags(&["staking", "slow"]),
+        TestCase::new("unlock_boundary", |env| async move {
+            test_unlock_boundary(&env).await
+        })
+        .tags(&["staking", "slow"]),
+        TestCase::new("storage", |env| async move { test_storage(&env).await })
+            .tags(&["staking", "storage"]),
+        TestCase::new(
+            "state_patch",
+            |env| async move { test_state_patch(&env).await },
+        )
+        .tags(&["staking", "storage"]),
+        TestCase::new(
+            "pool_model",
+            |env| async move { test_pool_model(&env).await },
+        )
+        .tags(&["staking", "model"]),
+        TestCase::new("validator_reward", |env| async move {
+            test_validator_reward(&env).await
+        })
+        .tags(&["staking", "rewards", "slow"]),
+        TestCase::new("farm_lifecycle", |env| async move {
+            test_farm_lifecycle(&env).await
+        })
+        .tags(&["farm"])
+        .requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH))
+        .requires(Requirement::Wasm(LOCKUP_WASM_FILEPATH)),
+        TestCase::new("overlapping_farms", |env| async move {
+            test_overlapping_farms(&env).await
+        })
+        .tags(&["farm"])
+        .requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH)),
+        TestCase::new("invalid_farm_dates", |env| async move {
+            test_invalid_farm_dates(&env).await
+        })
+        .tags(&["farm"])
+        .requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH)),
+    ];
+
+    tests.push(
+        TestCase::new("upgrade", |env| async move { test_upgrade(&env).await })
+            .tags(&["upgrade", "farm"])
+            .requires(Requirement::Wasm(STAKING_FARM_OLD_WASM_FILEPATH))
+            .requires(Requirement::Wasm(FACTORY_WASM_FILEPATH))
+            .requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH)),
+    );
+    tests.push(
+        TestCase::new("self_upgrade", |env| async move {
+            test_self_upgrade(&env).await
+        })
+        .tags(&["upgrade", "farm"])
+        .requires(Requirement::Wasm(FACTORY_WASM_FILEPATH))
+        .requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH)),
+    );
+    tests.extend(access_control_tests());
+
+    for scenario in load_scenarios(Path::new(SCENARIOS_DIR))? {
+        let name = format!("scenario:{}", scenario.name);
+        let uses_token = scenario
+            .steps
+            .iter()
+            .any(|step| matches!(step.action, Action::CreateFarm { .. }));
+        let scenario = Arc::new(scenario);
+
+        let mut test = TestCase::new(&name, move |env| {
+            let scenario = scenario.clone();
+            async move { run_scenario(&env, &scenario).await }
+        })
+        .tags(&["scenario"]);
+
+        if uses_token {
+            test = test.requires(Requirement::Wasm(FUNGIBLE_TOKEN_WASM_FILEPATH));
+        }
+
+        tests.push(test);
+    }
+
+    Ok(tests)
+}
+
+#[allow(unused_must_use)]
+pub async fn test_deposit_stake_unstake(
+    worker: &Worker<Sandbox>,
+    user: &Account,
+    staking_farm_contract: &Contract,
+    validator_contract: &Contract,
+    farm_unowned_stake: UnownedStake,
+) -> anyhow::Result<()> {
+    log!("Start: test_deposit_stake_unstake");
+
+    let farm = StakingFarmClient::new(staking_farm_contract, user);
+    let validator = ValidatorPoolClient::new(validator_contract, user);
+
+    let pool_summary = farm.get_pool_summary().await?;
+    log!("pool_summary {:#?}", pool_summary);
+
+    let farm_account = staking_farm_contract.as_account();
+    let mut invariants = InvariantChecker::new(staking_farm_contract, validator_contract)
+        .unowned_stake(farm_unowned_stake);
+    let snapshotter = Snapshotter::new(worker, staking_farm_contract, validator_contract)
+        .account("user", user.id());
+
+    // DEPOSIT #################
+    let before = snapshotter.take().await?;
+    farm.deposit(parse_near!("1000 N")).await?;
+    invariants.check("deposit").await?;
+
+    let diff = before.diff(&snapshotter.take().await?);
+    diff.print();
+    diff.assert_deltas(
+        &[
+            ("farm.user.unstaked_balance", parse_near!("1000 N") as i128),
+            ("farm.user.staked_balance", 0),
+            (
+                "validator.farm.unstaked_balance",
+                parse_near!("1000 N") as i128,
+            ),
+            ("validator.farm.staked_balance", 0),
+            ("pool.total_staked_balance", 0),
+        ],
+        0,
+    );
+    // the user also pays for gas, the validator receives the deposit
+    diff.assert_deltas(
+        &[
+            ("native.user", -(parse_near!("1000 N") as i128)),
+            ("native.validator", parse_near!("1000 N") as i128),
+        ],
+        parse_near!("0.1 N"),
+    );
+
+    let account = farm.get_account(user.id()).await?;
+    let total_balance = farm.get_account_total_balance(user.id()).await?;
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+    let validator_total_balance = validator
+        .get_account_total_balance(farm_account.id())
+        .await?;
+    let validator_staked_balance = validator
+        .get_account_staked_balance(farm_account.id())
+        .await?;
+    let validator_unstaked_balance = validator
+        .get_account_unstaked_balance(farm_account.id())
+        .await?;
+
+    assert_eq!(total_balance, parse_near!("1000 N"));
+    assert_eq!(account.staked_balance.0, parse_near!("0 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("1000 N"));
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.staked_balance.0, staked_balance);
+    assert_eq!(account.unstaked_balance.0, unstaked_balance);
+    assert_eq!(validator_total_balance, parse_near!("1000 N"));
+    assert_eq!(validator_staked_balance, parse_near!("0 N"));
+    assert_eq!(validator_unstaked_balance, parse_near!("1000 N"));
+
+    // STAKE #################
+    let before = snapshotter.take().await?;
+    farm.stake(parse_near!("200 N")).await?;
+    invariants.check("stake").await?;
+
+    before.diff(&snapshotter.take().await?).assert_deltas(
+        &[
+            ("farm.user.staked_balance", parse_near!("200 N") as i128),
+            (
+                "farm.user.unstaked_balance",
+                -(parse_near!("200 N") as i128),
+            ),
+            (
+                "validator.farm.staked_balance",
+                parse_near!("200 N") as i128,
+            ),
+            (
+                "validator.farm.unstaked_balance",
+                -(parse_near!("200 N") as i128),
+            ),
+            ("pool.total_staked_balance", parse_near!("200 N") as i128),
+        ],
+        0,
+    );
+
+    let account = farm.get_account(user.id()).await?;
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+    let validator_staked_balance = validator
+        .get_account_staked_balance(farm_account.id())
+        .await?;
+    let validator_unstaked_balance = validator
+        .get_account_unstaked_balance(farm_account.id())
+        .await?;
+
+    assert_eq!(account.staked_balance.0, parse_near!("200 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("800 N"));
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.staked_balance.0, staked_balance);
+    assert_eq!(account.unstaked_balance.0, unstaked_balance);
+    assert_eq!(validator_staked_balance, parse_near!("200 N"));
+    assert_eq!(validator_unstaked_balance, parse_near!("800 N"));
+
+    // STAKE_ALL #################
+    farm.stake_all().await?;
+    invariants.check("stake_all").await?;
+
+    let account = farm.get_account(user.id()).await?;
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+    let validator_staked_balance = validator
+        .get_account_staked_balance(farm_account.id())
+        .await?;
+    let validator_unstaked_balance = validator
+        .get_account_unstaked_balance(farm_account.id())
+        .await?;
+
+    assert_eq!(account.staked_balance.0, parse_near!("1000 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("0 N"));
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.staked_balance.0, staked_balance);
+    assert_eq!(account.unstaked_balance.0, unstaked_balance);
+    assert_eq!(validator_staked_balance, parse_near!("1000 N"));
+    assert_eq!(validator_unstaked_balance, parse_near!("0 N"));
+
+    // UNSTAKE #################
+    farm.unstake(parse_near!("100 N")).await?;
+    invariants.check("unstake").await?;
+
+    let account = farm.get_account(user.id()).await?;
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+    let validator_staked_balance = validator
+        .get_account_staked_balance(farm_account.id())
+        .await?;
+    let validator_unstaked_balance = validator
+        .get_account_unstaked_balance(farm_account.id())
+        .await?;
+
+    assert_eq!(account.staked_balance.0, parse_near!("900 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("100 N"));
+    assert_eq!(account.can_withdraw, false);
+    assert_eq!(account.staked_balance.0, staked_balance);
+    assert_eq!(account.unstaked_balance.0, unstaked_balance);
+    assert_eq!(validator_staked_balance, parse_near!("900 N"));
+    assert_eq!(validator_unstaked_balance, parse_near!("100 N"));
+
+    // UNSTAKE_ALL #################
+    farm.unstake_all().await?;
+    invariants.check("unstake_all").await?;
+
+    let account = farm.get_account(user.id()).await?;
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+    let validator_staked_balance = validator
+        .get_account_staked_balance(farm_account.id())
+        .await?;
+    let validator_unstaked_balance = validator
+        .get_account_unstaked_balance(farm_account.id())
+        .await?;
+
+    assert_eq!(account.staked_balance.0, parse_near!("0 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("1000 N"));
+    assert_eq!(account.can_withdraw, false);
+    assert_eq!(account.staked_balance.0, staked_balance);
+    assert_eq!(account.unstaked_balance.0, unstaked_balance);
+    assert_eq!(validator_staked_balance, parse_near!("0 N"));
+    assert_eq!(validator_unstaked_balance, parse_near!("1000 N"));
+
+    // DEPOSIT_AND_STAKE #################
+    farm.deposit_and_stake(parse_near!("1000 N")).await?;
+    invariants.check("deposit_and_stake").await?;
+
+    let staked_balance = farm.get_account_staked_balance(user.id()).await?;
+    let unstaked_balance = farm.get_account_unstaked_balance(user.id()).await?;
+
+    assert_eq!(staked_balance, parse_near!("1000 N"));
+    assert_eq!(unstaked_balance, parse_near!("1000 N"));
+
+    log!("Passed ✅ test_deposit_stake_unstake");
+    Ok(())
+}
+
+pub async fn test_error_paths(
+    user: &Account,
+    staking_farm_contract: &Contract,
+) -> anyhow::Result<()> {
+    log!("Start: test_error_paths");
+
+    let farm = StakingFarmClient::new(staking_farm_contract, user);
+
+    // 1000 N staked and 1000 N unstaked that is still locked
+    farm.deposit_and_stake(parse_near!("2000 N")).await?;
+    farm.unstake(parse_near!("1000 N")).await?;
+
+    assert_call_fails_with(
+        farm.withdraw(parse_near!("100 N")),
+        "The unstaked balance is not yet available due to unstaking delay",
+    )
+    .await;
+
+    assert_call_fails_with(
+        farm.stake(parse_near!("5000 N")),
+        "Not enough unstaked balance to stake",
+    )
+    .await;
+
+    assert_call_fails_with(
+        farm.claim(user.id(), None).deposit(0),
+        "Requires attached deposit of exactly 1 yoctoNEAR",
+    )
+    .await;
+
+    let account = farm.get_account(user.id()).await?;
+
+    assert_eq!(account.staked_balance.0, parse_near!("1000 N"));
+    assert_eq!(account.unstaked_balance.0, parse_near!("1000 N"));
+
+    log!("Passed ✅ test_error_paths");
+    Ok(())
+}
+
+#[allow(unused_must_use)]
+pub async fn test_withdraw(
+    worker: &Worker<Sandbox>,
+    user: &Account,
+    staking_farm_contract: &Contract,
+    validator_contract: &Contract,
+    farm_unowned_stake: UnownedStake,
+) -> anyhow::Result<()> {
+    log!("Start: test_withdraw");
+
+    let farm = StakingFarmClient::new(staking_farm_contract, user);
+    let mut invariants = InvariantChecker::new(staking_farm_contract, validator_contract)
+        .unowned_stake(farm_unowned_stake);
+
+    farm.deposit_and_stake(parse_near!("1000 N")).await?;
+    farm.unstake_all().await?;
+
+    wait_epochs(worker, 5).await?;
+
+    let pool_summary = farm.get_pool_summary().await?;
+    log!("pool_summary {:#?}", pool_summary);
+
+    let can_withdraw = farm.is_contract_can_withdraw().await?;
+    log!("Can withdraw {}", can_withdraw);
+
+    let account = farm.get_account(user.id()).await?;
+    log!("Account state: {:?}", account);
+
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.unstaked_balance.0, parse_near!("1000 N"));
+
+    // WITHDRAW 200 NEAR ##############
+    log!("Withdrawing 200 NEAR...");
+    farm.withdraw(parse_near!("200 N")).await?;
+    invariants.check("withdraw").await?;
+
+    let account = farm.get_account(user.id()).await?;
+
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.unstaked_balance.0, parse_near!("800 N"));
+
+    // WITHDRAW_ALL ##############
+    log!("Withdrawing the rest of NEAR...");
+    let prev_total_balance = farm.get_account_total_balance(user.id()).await?;
+    farm.withdraw_all().await?;
+    invariants.check("withdraw_all").await?;
+
+    let account = farm.get_account(user.id()).await?;
+    let total_balance = farm.get_account_total_balance(user.id()).await?;
+
+    assert_eq!(account.can_withdraw, true);
+    assert_eq!(account.unstaked_balance.0, 0);
+    assert_eq!(prev_total_balance - total_balance, parse_near!("800 N"));
+
+    log!("Passed ✅ test_withdraw");
+    Ok(())
+}
+
+/// Unstaked balance must stay locked for exactly `NUM_EPOCHS_TO_UNLOCK` epochs.
+pub async fn test_unlock_boundary(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_unlock_boundary");
+
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, &env.alice);
+
+    farm.deposit_and_stake(parse_near!("1000 N")).await?;
+
+    // unstake at the start of an epoch, so it can't straddle an epoch boundary
+    let unstake_epoch = wait_epoch(&env.worker).await?;
+    farm.unstake(parse_near!("500 N")).await?;
+
+    wait_until_epoch(&env.worker, unstake_epoch + NUM_EPOCHS_TO_UNLOCK - 1).await?;
+
+    let account = farm.get_account(env.alice.id()).await?;
+    assert_eq!(account.can_withdraw, false);
+    assert_call_fails_with(
+        farm.withdraw(parse_near!("500 N")),
+        "The unstaked balance is not yet available due to unstaking delay",
+    )
+    .await;
+
+    wait_until_epoch(&env.worker, unstake_epoch + NUM_EPOCHS_TO_UNLOCK).await?;
+
+    let account = farm.get_account(env.alice.id()).await?;
+    assert_eq!(account.can_withdraw, true);
+    farm.withdraw(parse_near!("500 N")).await?;
+
+    let account = farm.get_account(env.alice.id()).await?;
+    assert_eq!(account.unstaked_balance.0, 0);
+
+    log!("Passed ✅ test_unlock_boundary");
+    Ok(())
+}
+
+/// Decodes the raw storage of the validator before and after a stake and checks the diff
+/// shows the farm's account in the validator.
+pub async fn test_storage(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_storage");
+
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, &env.alice);
+
+    let validator_before = dump_storage(&env.validator_contract, StorageLayout::Validator).await?;
+    let farm_before = dump_storage(&env.staking_farm_contract, StorageLayout::StakingFarm).await?;
+
+    farm.deposit_and_stake(parse_near!("1000 N")).await?;
+
+    let validator_after = dump_storage(&env.validator_contract, StorageLayout::Validator).await?;
+    let farm_after = dump_storage(&env.staking_farm_contract, StorageLayout::StakingFarm).await?;
+
+    validator_after.print();
+    assert!(
+        validator_before.errors.is_empty() && validator_after.errors.is_empty(),
+        "validator storage doesn't match its layout"
+    );
+    farm_after.print();
+    assert!(
+        farm_before.errors.is_empty() && farm_after.errors.is_empty(),
+        "farm storage doesn't match its layout"
+    );
+
+    log!("Validator storage changes:");
+    let changes = validator_before.diff(&validator_after);
+    print_storage_diff(&changes);
+    let farm_account = format!("accounts[{}]", farm.id());
+    assert!(changes.iter().any(|change| change.key == farm_account));
+
+    log!("Farm storage changes:");
+    let changes = farm_before.diff(&farm_after);
+    print_storage_diff(&changes);
+    let alice_account = format!("accounts[{}]", env.alice.id());
+    assert!(changes.iter().any(|change| change.key == alice_account));
+
+    log!("Passed ✅ test_storage");
+    Ok(())
+}
+
+/// Starts accounts off unlocked, locked and staked by patching the farm and the validator,
+/// and withdraws the unlocked balance right away instead of waiting out the unstake epochs.
+pub async fn test_state_patch(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_state_patch");
+
+    let worker = &env.worker;
+    let funder = env.create_account("funder", parse_near!("5000 N")).await?;
+    let bob = env.create_account("bob", parse_near!("10 N")).await?;
+    let carol = env.create_account("carol", parse_near!("10 N")).await?;
+    let dave = env.create_account("dave", parse_near!("10 N")).await?;
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, &bob);
+    let mut invariants = InvariantChecker::new(&env.staking_farm_contract, &env.validator_contract)
+        .unowned_stake(env.farm_unowned_stake);
+
+    // UNLOCKED #################
+    patch_farm_account(
+        worker,
+        &funder,
+        &env.staking_farm_contract,
+        &env.validator_contract,
+        bob.id(),
+        StakeState::unlocked(parse_near!("1000 N")),
+    )
+    .await?;
+    invariants.check("patch unlocked").await?;
+
+    let account = farm.get_account(bob.id()).await?;
+    assert_eq!(account.unstaked_balance.0, parse_near!("1000 N"));
+    assert_eq!(account.staked_balance.0, 0);
+    assert!(account.can_withdraw);
+
+    let balance_before = bob.view_account().await?.balance;
+    farm.withdraw_all().await?;
+    invariants.check("withdraw patched").await?;
+    let withdrawn = bob.view_account().await?.balance - balance_before;
+    assert!(withdrawn > parse_near!("999.9 N"));
+
+    // LOCKED #################
+    let epoch_height = epoch_info(worker).await?.epoch_height;
+    patch_farm_account(
+        worker,
+        &funder,
+        &env.staking_farm_contract,
+        &env.validator_contract,
+        carol.id(),
+        StakeState::locked(parse_near!("500 N"), epoch_height + NUM_EPOCHS_TO_UNLOCK),
+    )
+    .await?;
+    invariants.check("patch locked").await?;
+
+    let account = farm.get_account(carol.id()).await?;
+    assert_eq!(account.unstaked_balance.0, parse_near!("500 N"));
+    assert!(!account.can_withdraw);
+
+    // STAKED #################
+    let total_staked_before = farm.get_total_staked_balance().await?;
+    patch_farm_account(
+        worker,
+        &funder,
+        &env.staking_farm_contract,
+        &env.validator_contract,
+        dave.id(),
+        StakeState::staked(parse_near!("300 N")),
+    )
+    .await?;
+    invariants.check("patch staked").await?;
+
+    let staked = farm.get_account_staked_balance(dave.id()).await?;
+    let total_staked = farm.get_total_staked_balance().await?;
+    assert!(staked > 0);
+    let added = total_staked - total_staked_before;
+    assert!(added + parse_near!("0.001 N") >= staked && staked + parse_near!("0.001 N") >= added);
+
+    // BROKEN BOOKS #################
+    // balance the validator holds for the farm that no farm account has
+    let mut farm_stake =
+        read_validator_stake(&env.validator_contract, env.staking_farm_contract.id()).await?;
+    farm_stake.unstaked += parse_near!("100 N");
+    patch_validator_account(
+        worker,
+        &funder,
+        &env.validator_contract,
+        env.staking_farm_contract.id(),
+        farm_stake,
+    )
+    .await?;
+    let err = invariants
+        .check("patch validator only")
+        .await
+        .expect_err("unstaked balance missing from the farm's books");
+    log!("{}", err);
+    assert_eq!(err.action, "patch validator only");
+    assert!(err.violations.iter().any(|violation| violation
+        .invariant
+        .starts_with("sum of accounts' unstaked balance")));
+
+    log!("Passed ✅ test_state_patch");
+    Ok(())
+}
+
+/// Runs staking actions of two users and the owner through the farm and the `PoolModel`
+/// side by side, comparing every account and the pool summary after each one.
+pub async fn test_pool_model(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_pool_model");
+
+    let bob = env.create_account("bob", parse_near!("10000 N")).await?;
+    let alice_farm = StakingFarmClient::new(&env.staking_farm_contract, &env.alice);
+    let bob_farm = alice_farm.with_signer(&bob);
+    let owner_farm = alice_farm.with_signer(&env.owner);
+
+    let mut model = PoolModel::new(
+        env.owner.id(),
+        alice_farm.get_reward_fee_fraction().await?,
+        alice_farm.get_total_staked_balance().await?,
+    );
+    assert_model_matches(&model, &alice_farm, "init").await?;
+
+    let (alice, bob_id) = (env.alice.id(), bob.id());
+
+    alice_farm.deposit(parse_near!("1000 N")).await?;
+    model.deposit(alice, parse_near!("1000 N")).unwrap();
+    assert_model_matches(&model, &alice_farm, "alice deposit").await?;
+
+    alice_farm.stake(parse_near!("600 N")).await?;
+    model.stake(alice, parse_near!("600 N")).unwrap();
+    assert_model_matches(&model, &alice_farm, "alice stake").await?;
+
+    bob_farm.deposit_and_stake(parse_near!("500 N")).await?;
+    model
+        .deposit_and_stake(bob_id, parse_near!("500 N"))
+        .unwrap();
+    assert_model_matches(&model, &alice_farm, "bob deposit_and_stake").await?;
+
+    let error = model.stake(alice, parse_near!("5000 N")).unwrap_err();
+    assert_call_fails_with(alice_farm.stake(parse_near!("5000 N")), error).await;
+    assert_model_matches(&model, &alice_farm, "alice stake too much").await?;
+
+    alice_farm.unstake(parse_near!("200 N")).await?;
+    model.unstake(alice, parse_near!("200 N")).unwrap();
+    assert_model_matches(&model, &alice_farm, "alice unstake").await?;
+
+    let error = model.withdraw(alice, parse_near!("100 N")).unwrap_err();
+    assert_call_fails_with(alice_farm.withdraw(parse_near!("100 N")), error).await;
+
+    bob_farm.unstake_all().await?;
+    model.unstake_all(bob_id).unwrap();
+    assert_model_matches(&model, &alice_farm, "bob unstake_all").await?;
+
+    let reward_fee_fraction = Ratio {
+        numerator: 1,
+        denominator: 10,
+    };
+    owner_farm
+        .update_reward_fee_fraction(reward_fee_fraction.clone())
+        .await?;
+    model.update_reward_fee_fraction(reward_fee_fraction);
+    assert_model_matches(&model, &alice_farm, "update_reward_fee_fraction").await?;
+
+    alice_farm.stake_all().await?;
+    model.stake_all(alice).unwrap();
+    assert_model_matches(&model, &alice_farm, "alice stake_all").await?;
+
+    log!("Passed ✅ test_pool_model");
+    Ok(())
+}
+
+/// Simulates validator rewards and checks that the farm's accounts and owner get them, the
+/// owner's fee being the farm's reward fee fraction.
+pub async fn test_validator_reward(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_validator_reward");
+
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, &env.alice);
+    let reward_fee_fraction = farm.get_reward_fee_fraction().await?;
+    let mut model = PoolModel::new(
+        env.owner.id(),
+        reward_fee_fraction.clone(),
+        farm.get_total_staked_balance().await?,
+    );
+
+    farm.deposit_and_stake(parse_near!("1000 N")).await?;
+    model
+        .deposit_and_stake(env.alice.id(), parse_near!("1000 N"))
+        .unwrap();
+
+    let total_staked_before = farm.get_total_staked_balance().await?;
+    let owner_staked_before = farm.get_account_staked_balance(env.owner.id()).await?;
+
+    let reward = simulate_validator_reward(
+        &env.worker,
+        &env.owner,
+        &env.validator_contract,
+        &env.staking_farm_contract,
+        parse_near!("100 N"),
+    )
+    .await?;
+    assert!(reward.farm_reward > 0, "the farm got no reward");
+
+    model.new_epochs(1);
+    model.distribute_reward(reward.farm_reward);
+    assert_model_matches(&model, &farm, "validator reward").await?;
+
+    let total_staked = farm.get_total_staked_balance().await?;
+    assert_eq!(total_staked - total_staked_before, reward.farm_reward);
+
+    let owners_fee = mul_div(
+        reward.farm_reward,
+        reward_fee_fraction.numerator as u128,
+        reward_fee_fraction.denominator as u128,
+    );
+    // the fee buys stake shares, rounded down, on top of the owner's share of the rest
+    let owners_reward = mul_div(
+        owner_staked_before,
+        reward.farm_reward - owners_fee,
+        total_staked_before,
+    );
+    let owner_staked = farm.get_account_staked_balance(env.owner.id()).await?;
+    assert_almost_eq(
+        owner_staked - owner_staked_before,
+        owners_fee + owners_reward,
+        10,
+    );
+
+    let alice_staked = farm.get_account_staked_balance(env.alice.id()).await?;
+    assert!(alice_staked > parse_near!("1000 N"));
+
+    log!("Passed ✅ test_validator_reward");
+    Ok(())
+}
+
+async fn assert_model_matches(
+    model: &PoolModel,
+    farm: &StakingFarmClient,
+    action: &str,
+) -> anyhow::Result<()> {
+    let mismatches = model.compare_with_farm(farm).await?;
+
+    for mismatch in &mismatches {
+        log!("{}", mismatch);
+    }
+    assert!(
+        mismatches.is_empty(),
+        "farm differs from the model after {}",
+        action
+    );
+
+    Ok(())
+}
+
+pub async fn test_farm_lifecycle(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_farm_lifecycle");
+
+    let worker = &env.worker;
+    let owner = &env.owner;
+
+    let bob = env.create_account("bob", parse_near!("10000 N")).await?;
+    let carol = env.create_account("carol", parse_near!("10000 N")).await?;
+    let dave = env.create_account("dave", parse_near!("100 N")).await?;
+    let lockup = deploy_lockup(
+        worker,
+        &env.account_name("lockup"),
+        &dave,
+        parse_near!("10000 N"),
+    )
+    .await?;
+    let token = deploy_fungible_token(worker, &env.account_name("token"), owner).await?;
+
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, owner);
+    let mut invariants = InvariantChecker::new(&env.staking_farm_contract, &env.validator_contract)
+        .unowned_stake(env.farm_unowned_stake);
+    let farm_amount = parse_near!("100000 N");
+
+    token
+        .register_accounts(&[owner.id(), farm.id(), bob.id(), carol.id(), dave.id()])
+        .await?;
+    token.mint(owner.id(), farm_amount).await?;
+
+    // STAKE #################
+    farm.with_signer(&bob)
+        .deposit_and_stake(parse_near!("1000 N"))
+        .await?;
+    farm.with_signer(&carol)
+        .deposit_and_stake(parse_near!("3000 N"))
+        .await?;
+    lockup
+        .deposit_and_stake(farm.id(), parse_near!("1000 N"))
+        .await?;
+    invariants.check("stake").await?;
+
+    assert_eq!(
+        farm.get_account_staked_balance(lockup.id()).await?,
+        parse_near!("1000 N")
+    );
+
+    // CREATE FARM #################
+    farm.add_authorized_farm_token(token.id()).await?;
+    assert!(farm
+        .get_authorized_farm_tokens()
+        .await?
+        .contains(token.id()));
+
+    let farm_id = farm
+        .create_farm_and_get_id(worker, &FarmSpec::new(&token, farm_amount))
+        .await?;
+    let created = farm.get_farm(farm_id).await?;
+
+    assert_eq!(created.token_id, *token.id());
+    assert_eq!(created.amount.0, farm_amount);
+    assert_eq!(token.ft_balance_of(farm.id()).await?, farm_amount);
+    assert_eq!(token.ft_balance_of(owner.id()).await?, 0);
+
+    // ACCRUE #################
+    fast_forward_to_farm_start(worker, &created).await?;
+    fast_forward_by(worker, Duration::from_secs(20)).await?;
+
+    let bob_reward = farm.get_unclaimed_reward(bob.id(), farm_id).await?;
+    let carol_reward = farm.get_unclaimed_reward(carol.id(), farm_id).await?;
+
+    assert!(bob_reward > 0);
+    assert_almost_eq(carol_reward, bob_reward * 3, parse_near!("1 N"));
+
+    // The views above and the block below may be a couple of sessions apart.
+    let now = worker.view_block().await?.timestamp();
+    let accounts = farm.get_accounts(0, 100).await?;
+    let accounts_stake: u128 = accounts.iter().map(|a| a.staked_balance.0).sum();
+    let model = RewardModel::new(
+        FarmParams::from(&created),
+        owner.id(),
+        Ratio {
+            numerator: 1,
+            denominator: 2,
+        },
+    )
+    .unowned_stake(farm.get_total_staked_balance().await? - accounts_stake)
+    .with_accounts(0, &accounts);
+    let expected_bob_reward = model.unclaimed_reward(bob.id(), now);
+    let drift = model.unclaimed_reward(bob.id(), now + 2 * SESSION_INTERVAL) - expected_bob_reward;
+
+    assert_almost_eq(bob_reward, expected_bob_reward, drift + parse_near!("1 N"));
+
+    worker.fast_forward(20).await?;
+
+    let bob_reward_later = farm.get_unclaimed_reward(bob.id(), farm_id).await?;
+    let lockup_reward = farm.get_unclaimed_reward(lockup.id(), farm_id).await?;
+
+    assert!(bob_reward_later > bob_reward);
+    assert_almost_eq(lockup_reward, bob_reward_later, parse_near!("1 N"));
+
+    // CLAIM #################
+    farm.with_signer(&bob).claim(token.id(), None).await?;
+
+    assert!(token.ft_balance_of(bob.id()).await? >= bob_reward_later);
+    assert!(farm.get_unclaimed_reward(bob.id(), farm_id).await? < bob_reward_later);
+
+    // CLAIM FOR DELEGATOR #################
+    farm.with_signer(&dave)
+        .claim(token.id(), Some(lockup.id()))
+        .await?;
+
+    assert!(token.ft_balance_of(dave.id()).await? >= lockup_reward);
+    assert!(farm.get_unclaimed_reward(lockup.id(), farm_id).await? < lockup_reward);
+
+    farm.with_signer(&carol).claim(token.id(), None).await?;
+    invariants.check("claim").await?;
+
+    // STOP FARM #################
+    farm.stop_farm(farm_id).await?;
+
+    assert!(farm
+        .get_active_farms()
+        .await?
+        .iter()
+        .all(|f| f.farm_id != farm_id));
+
+    let returned = token.ft_balance_of(owner.id()).await?;
+    let claimed = token.ft_balance_of(bob.id()).await?
+        + token.ft_balance_of(carol.id()).await?
+        + token.ft_balance_of(dave.id()).await?;
+    let left_in_farm = token.ft_balance_of(farm.id()).await?;
+
+    assert!(returned > 0);
+    assert_eq!(returned + claimed + left_in_farm, farm_amount);
+
+    log!("Passed ✅ test_farm_lifecycle");
+    Ok(())
+}
+
+/// Two farms of the same token whose windows overlap are both active with the dates they
+/// were created with.
+pub async fn test_overlapping_farms(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_overlapping_farms");
+
+    let owner = &env.owner;
+    let token = deploy_fungible_token(&env.worker, &env.account_name("token"), owner).await?;
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, owner);
+    let farm_amount = parse_near!("1000 N");
+
+    token.register_accounts(&[owner.id(), farm.id()]).await?;
+    token.mint(owner.id(), farm_amount * 2).await?;
+    farm.add_authorized_farm_token(token.id()).await?;
+
+    let now = env.worker.view_block().await?.timestamp();
+    let specs = [
+        FarmSpec::new(&token, farm_amount)
+            .name("first")
+            .start_at(now + 10 * ONE_SEC_IN_NANOSECONDS)
+            .end_at(now + 110 * ONE_SEC_IN_NANOSECONDS),
+        FarmSpec::new(&token, farm_amount)
+            .name("second")
+            .start_at(now + 60 * ONE_SEC_IN_NANOSECONDS)
+            .duration(Duration::from_secs(100)),
+    ];
+
+    let mut farm_ids = vec![];
+    for spec in &specs {
+        let farm_id = farm.create_farm_and_get_id(&env.worker, spec).await?;
+        let created = farm.get_farm(farm_id).await?;
+        let expected = spec.msg(now);
+
+        assert_eq!(created.name, expected.name);
+        assert_eq!(created.start_date, expected.start_date);
+        assert_eq!(created.end_date, expected.end_date);
+        assert_eq!(created.amount.0, farm_amount);
+        farm_ids.push(farm_id);
+    }
+
+    assert_ne!(farm_ids[0], farm_ids[1]);
+    let active = farm.get_active_farms().await?;
+    for farm_id in &farm_ids {
+        assert!(active.iter().any(|farm| farm.farm_id == *farm_id));
+    }
+
+    log!("Passed ✅ test_overlapping_farms");
+    Ok(())
+}
+
+/// Creates a farm that ended before it is created and one that ends when it starts, and
+/// checks that the farm rejects both, refunding the tokens.
+pub async fn test_invalid_farm_dates(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_invalid_farm_dates");
+
+    let owner = &env.owner;
+    let token = deploy_fungible_token(&env.worker, &env.account_name("token"), owner).await?;
+    let farm = StakingFarmClient::new(&env.staking_farm_contract, owner);
+    let farm_amount = parse_near!("1000 N");
+
+    token.register_accounts(&[owner.id(), farm.id()]).await?;
+    token.mint(owner.id(), farm_amount).await?;
+    farm.add_authorized_farm_token(token.id()).await?;
+
+    let now = env.worker.view_block().await?.timestamp();
+    let specs = vec![
+        (
+            FarmSpec::new(&token, farm_amount)
+                .name("past")
+                .start_at(now - 100 * ONE_SEC_IN_NANOSECONDS)
+                .end_at(now - 10 * ONE_SEC_IN_NANOSECONDS),
+            "ERR_FARM_TOO_EARLY",
+        ),
+        (
+            FarmSpec::new(&token, farm_amount)
+                .name("zero_duration")
+                .start_at(now + 60 * ONE_SEC_IN_NANOSECONDS)
+                .duration(Duration::from_secs(0)),
+            "ERR_FARM_DATE",
+        ),
+    ];
+
+    for (spec, error) in &specs {
+        let farms_before = farm.get_farms(0, 100).await?.len();
+
+        assert_call_fails_with(farm.create_farm(&env.worker, spec), *error).await;
+
+        assert_eq!(farm.get_farms(0, 100).await?.len(), farms_before);
+        assert_eq!(token.ft_balance_of(owner.id()).await?, farm_amount);
+        assert_eq!(token.ft_balance_of(farm.id()).await?, 0);
+    }
+
+    log!("Passed ✅ test_invalid_farm_dates");
+    Ok(())
+}
+
+/// Upgrades a farm running `STAKING_FARM_OLD_WASM_FILEPATH` with stakers, a pending unstake
+/// and a running farm to `STAKING_FARM_WASM_FILEPATH`, and checks that the version changed
+/// and nothing else did.
+pub async fn test_upgrade(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_upgrade");
+
+    let old_wasm = std::fs::read(STAKING_FARM_OLD_WASM_FILEPATH)?;
+    let (version_before, version_after) = upgrade_populated_farm(env, &old_wasm).await?;
+    assert_ne!(version_after, version_before);
+
+    log!("Passed ✅ test_upgrade");
+    Ok(())
+}
+
+/// Upgrades a populated farm running `STAKING_FARM_WASM_FILEPATH` to the same code and checks
+/// that its state survives the factory, `upgrade` and `migrate` path. Says nothing about
+/// upgrading from a previous release, that is `test_upgrade`.
+pub async fn test_self_upgrade(env: &TestEnv) -> anyhow::Result<()> {
+    log!("Start: test_self_upgrade");
+
+    let wasm = std::fs::read(STAKING_FARM_WASM_FILEPATH)?;
+    upgrade_populated_farm(env, &wasm).await?;
+
+    log!("Passed ✅ test_self_upgrade");
+    Ok(())
+}
+
+/// Deploy `old_wasm` through the factory, populate it, upgrade it to
+/// `STAKING_FARM_WASM_FILEPATH` and check that no view changed and the farm keeps working.
+/// Returns the versions before and after.
+async fn upgrade_populated_farm(
+    env: &TestEnv,
+    old_wasm: &[u8],
+) -> anyhow::Result<(String, String)> {
+    let worker = &env.worker;
+    let owner = &env.owner;
+
+    let factory_contract = worker
+        .dev_deploy(&std::fs::read(FACTORY_WASM_FILEPATH)?)
+        .await?;
+    let factory = FactoryClient::new(&factory_contract, factory_contract.as_account());
+    let code_hash = factory
+        .store(&std::fs::read(STAKING_FARM_WASM_FILEPATH)?)
+        .await?;
+
+    let staking_farm_contract =
+        deploy_farm_from_factory(worker, &factory, owner, &env.validator_contract, old_wasm)
+            .await?;
+    let farm = StakingFarmClient::new(&staking_farm_contract, owner);
+    assert_eq!(farm.get_factory_id().await?, *factory.id());
+
+    // POPULATE #################
+    let bob = env.create_account("bob", parse_near!("10000 N")).await?;
+    let carol = env.create_account("carol", parse_near!("10000 N")).await?;
+    let token = deploy_fungible_token(worker, &env.account_name("token"), owner).await?;
+    let farm_amount = parse_near!("100000 N");
+
+    token
+        .register_accounts(&[owner.id(), farm.id(), bob.id(), carol.id()])
+        .await?;
+    token.mint(owner.id(), farm_amount).await?;
+
+    farm.with_signer(&bob)
+        .deposit_and_stake(parse_near!("1000 N"))
+        .await?;
+    farm.with_signer(&carol)
+        .deposit_and_stake(parse_near!("2000 N"))
+        .await?;
+    farm.with_signer(&carol)
+        .unstake(parse_near!("500 N"))
+        .await?;
+
+    farm.add_authorized_user(bob.id()).await?;
+    farm.add_authorized_farm_token(token.id()).await?;
+    let farm_id = farm
+        .create_farm_and_get_id(
+            worker,
+            &FarmSpec::new(&token, farm_amount).duration(Duration::from_secs(86400)),
+        )
+        .await?;
+    fast_forward_to_farm_start(worker, &farm.get_farm(farm_id).await?).await?;
+    fast_forward_by(worker, Duration::from_secs(10)).await?;
+
+    // UPGRADE #################
+    let version_before = farm.get_version().await?;
+    let state_before = FarmState::capture(&farm).await?;
+
+    farm.upgrade(&code_hash).await?;
+
+    let version_after = farm.get_version().await?;
+    log!("Upgraded from {} to {}", version_before, version_after);
+
+    let changes = FarmState::capture(&farm)
+        .await?
+        .changes_since(&state_before);
+    for change in &changes {
+        log!("{}", change);
+    }
+    assert!(changes.is_empty(), "the upgrade changed the farm's state");
+
+    // the upgraded farm keeps working
+    farm.with_signer(&bob)
+        .deposit_and_stake(parse_near!("100 N"))
+        .await?;
+    farm.with_signer(&bob).claim(token.id(), None).await?;
+
+    Ok((version_before, version_after))
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::*;
+
+/// Where a fast-forward ended.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeTravel {
+    pub block_height: u64,
+    /// Block timestamp in nanoseconds.
+    pub timestamp: u64,
+    pub skipped_blocks: u64,
+}
+
+/// Blocks to fast-forward for `duration` to pass, given the nanoseconds per block.
+pub fn estimate_blocks(duration: Duration, block_time: u64) -> u64 {
+    let block_time = std::cmp::max(block_time, 1) as u128;
+
+    ((duration.as_nanos() + block_time - 1) / block_time) as u64
+}
+
+/// Fast-forward until the first block whose timestamp is at least `timestamp` (in
+/// nanoseconds).
+///
+/// The first jump is a single block, which measures the block time. Every further jump is
+/// 90% of the blocks estimated from the last measured block time, so the target is
+/// approached from below and passed by at most one block.
+pub async fn fast_forward_to_timestamp(
+    worker: &Worker<Sandbox>,
+    timestamp: u64,
+) -> anyhow::Result<TimeTravel> {
+    let start = worker.view_block().await?;
+    let (mut height, mut now) = (start.height(), start.timestamp());
+    let mut block_time = None;
+
+    while now < timestamp {
+        let blocks = match block_time {
+            None => 1,
+            Some(block_time) => {
+                let blocks = estimate_blocks(Duration::from_nanos(timestamp - now), block_time);
+                std::cmp::max(blocks * 9 / 10, 1)
+            }
+        };
+        worker.fast_forward(blocks).await?;
+
+        let block = worker.view_block().await?;
+        if block.height() > height {
+            block_time = Some((block.timestamp() - now) / (block.height() - height));
+        }
+        height = block.height();
+        now = block.timestamp();
+    }
+
+    let travel = TimeTravel {
+        block_height: height,
+        timestamp: now,
+        skipped_blocks: height - start.height(),
+    };
+    log!(
+        "Fast-forwarded {} blocks to timestamp {} (target {})",
+        travel.skipped_blocks,
+        travel.timestamp,
+        timestamp
+    );
+
+    Ok(travel)
+}
+
+/// Fast-forward until `duration` of block time has passed.
+pub async fn fast_forward_by(
+    worker: &Worker<Sandbox>,
+    duration: Duration,
+) -> anyhow::Result<TimeTravel> {
+    let now = worker.view_block().await?.timestamp();
+
+    fast_forward_to_timestamp(worker, now + duration.as_nanos() as u64).await
+}
+
+/// Fast-forward until the farm has started. Does nothing if it already has.
+pub async fn fast_forward_to_farm_start(
+    worker: &Worker<Sandbox>,
+    farm: &HumanReadableFarm,
+) -> anyhow::Result<TimeTravel> {
+    fast_forward_to_timestamp(worker, farm.start_date.0).await
+}
+
+/// Fast-forward until the farm has ended and released all its rewards.
+pub async fn fast_forward_to_farm_end(
+    worker: &Worker<Sandbox>,
+    farm: &HumanReadableFarm,
+) -> anyhow::Result<TimeTravel> {
+    fast_forward_to_timestamp(worker, farm.end_date.0).await
+}
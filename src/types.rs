@@ -4,14 +4,14 @@ use near_sdk::{
 };
 use workspaces::AccountId;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct StorageBalanceBoundsJson {
     pub min: String,
     pub max: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct HumanReadableAccount {
     pub account_id: AccountId,
@@ -23,14 +23,14 @@ pub struct HumanReadableAccount {
     pub can_withdraw: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Ratio {
     pub numerator: u32,
     pub denominator: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct HumanReadableFarm {
     pub farm_id: u64,
@@ -42,7 +42,7 @@ pub struct HumanReadableFarm {
     pub active: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct PoolSummary {
     /// Pool owner.
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use near_sdk::Balance;
+use workspaces::AccountId;
+
+use crate::*;
+
+/// More accounts and farms than any test creates.
+const PAGE_SIZE: u64 = 1000;
+
+/// Client for `contracts/factory.wasm`, which serves farm code to `upgrade`.
+#[derive(Clone)]
+pub struct FactoryClient {
+    pub contract: Contract,
+    pub signer: Account,
+}
+
+impl FactoryClient {
+    pub fn new(contract: &Contract, signer: &Account) -> Self {
+        Self {
+            contract: contract.clone(),
+            signer: signer.clone(),
+        }
+    }
+
+    pub fn id(&self) -> &AccountId {
+        self.contract.id()
+    }
+
+    /// Store `code` in the factory and return its hash, as `upgrade` expects it.
+    pub async fn store(&self, code: &[u8]) -> Result<Vec<u8>, CallError> {
+        let outcome = ContractCall::new(&self.signer, self.contract.id(), "store")
+            .contract_name("factory")
+            .args(code.to_vec())
+            .gas(parse_gas!("300 T") as u64)
+            .await?;
+
+        Ok(outcome.value)
+    }
+}
+
+/// Deploy `wasm` as a new farm and initialize it from `factory`, which makes `factory` the
+/// farm's factory, the way a farm created by the real factory is set up.
+pub async fn deploy_farm_from_factory(
+    worker: &Worker<Sandbox>,
+    factory: &FactoryClient,
+    owner: &Account,
+    validator_contract: &Contract,
+    wasm: &[u8],
+) -> anyhow::Result<Contract> {
+    log!("Deploying farm from factory {}...", factory.id());
+
+    let staking_farm_contract = worker.dev_deploy(wasm).await?;
+    StakingFarmClient::new(&staking_farm_contract, factory.contract.as_account())
+        .init(
+            owner.id(),
+            validator_contract.id(),
+            Ratio {
+                numerator: 1,
+                denominator: 2,
+            },
+        )
+        .await?;
+
+    Ok(staking_farm_contract)
+}
+
+/// Everything an upgrade must preserve, read through the farm's view methods.
+#[derive(Debug, Clone)]
+pub struct FarmState {
+    /// JSON result of every view, keyed like `account:<id>`, `farm:<id>` or `owner_id`.
+    pub views: BTreeMap<String, serde_json::Value>,
+    /// `get_unclaimed_reward` per account and farm, which keeps growing while farms run.
+    pub unclaimed_rewards: BTreeMap<(AccountId, u64), Balance>,
+}
+
+impl FarmState {
+    pub async fn capture(farm: &StakingFarmClient) -> anyhow::Result<Self> {
+        let mut views = BTreeMap::new();
+        let mut unclaimed_rewards = BTreeMap::new();
+
+        let accounts = farm.get_accounts(0, PAGE_SIZE).await?;
+        let farms = farm.get_farms(0, PAGE_SIZE).await?;
+
+        for account in &accounts {
+            for farm_info in &farms {
+                let reward = farm
+                    .get_unclaimed_reward(&account.account_id, farm_info.farm_id)
+                    .await?;
+                unclaimed_rewards.insert((account.account_id.clone(), farm_info.farm_id), reward);
+            }
+            views.insert(
+                format!("account:{}", account.account_id),
+                serde_json::to_value(account)?,
+            );
+        }
+        for farm_info in &farms {
+            views.insert(
+                format!("farm:{}", farm_info.farm_id),
+                serde_json::to_value(farm_info)?,
+            );
+        }
+
+        views.insert("owner_id".into(), json!(farm.get_owner_id().await?));
+        views.insert("validator_id".into(), json!(farm.get_validator_id().await?));
+        views.insert("factory_id".into(), json!(farm.get_factory_id().await?));
+        views.insert(
+            "reward_fee_fraction".into(),
+            json!(farm.get_reward_fee_fraction().await?),
+        );
+        views.insert(
+            "authorized_users".into(),
+            json!(farm.get_authorized_users().await?),
+        );
+        views.insert(
+            "authorized_farm_tokens".into(),
+            json!(farm.get_authorized_farm_tokens().await?),
+        );
+        views.insert(
+            "total_staked_balance".into(),
+            json!(farm.get_total_staked_balance().await?.to_string()),
+        );
+
+        Ok(Self {
+            views,
+            unclaimed_rewards,
+        })
+    }
+
+    /// Views that differ from `before` and unclaimed rewards that went down since.
+    pub fn changes_since(&self, before: &FarmState) -> Vec<String> {
+        let mut changes = vec![];
+
+        let keys = before.views.keys().chain(self.views.keys());
+        for key in keys.collect::<std::collections::BTreeSet<_>>() {
+            let (old, new) = (before.views.get(key), self.views.get(key));
+            if old != new {
+                changes.push(format!("{}: {:?} -> {:?}", key, old, new));
+            }
+        }
+
+        for (key, old) in &before.unclaimed_rewards {
+            match self.unclaimed_rewards.get(key) {
+                Some(new) if new >= old => {}
+                new => changes.push(format!(
+                    "unclaimed reward of {} in farm {}: {} -> {:?}",
+                    key.0, key.1, old, new
+                )),
+            }
+        }
+
+        changes
+    }
+}
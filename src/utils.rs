@@ -1,37 +1,138 @@
-use workspaces::{result::ViewResultDetails, types::Balance};
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+
+use workspaces::{result::ViewResultDetails, types::Balance, types::Gas, AccountId};
 
 use crate::*;
 
-/// Check the result of a contract method call
-pub fn check_res(res: &ExecutionFinalResult, msg: &str) {
-    if res.is_failure() {
-        println!("{} | FAIL", msg);
-        println!("{:#?}", res);
-        panic!("FAIL: {}", msg);
-    } else if res.receipt_failures().len() > 0 {
-        println!("{} | FAIL", msg);
-        println!("RECEIPTS:");
-        res.receipt_outcomes()
-            .into_iter()
-            .for_each(|row| println!("{:?}", row));
-
-        println!("FAILURES:");
-        println!("{:?}", res.receipt_failures());
-
-        panic!("FAIL: {}", msg);
-    } else {
-        res.logs().into_iter().for_each(|row| println!("{:?}", row));
-        println!(
-            "{} | OK ({} TGas)",
-            msg,
-            res.total_gas_burnt / 1_000_000_000_000
+/// A change method call that has not been sent yet.
+///
+/// Gas, deposit and signer start from the defaults picked by the client that built the call
+/// and can be overridden before awaiting it.
+pub struct ContractCall {
+    signer: Account,
+    contract_id: AccountId,
+    /// Which kind of contract is called, used to key the gas report.
+    contract_name: Option<&'static str>,
+    method: String,
+    args: Vec<u8>,
+    gas: Gas,
+    deposit: Balance,
+}
+
+impl ContractCall {
+    pub fn new(signer: &Account, contract_id: &AccountId, method: &str) -> Self {
+        Self {
+            signer: signer.clone(),
+            contract_id: contract_id.clone(),
+            contract_name: None,
+            method: method.to_string(),
+            args: b"{}".to_vec(),
+            gas: parse_gas!("30 T") as u64,
+            deposit: 0,
+        }
+    }
+
+    pub fn contract_name(mut self, contract_name: &'static str) -> Self {
+        self.contract_name = Some(contract_name);
+        self
+    }
+
+    pub fn args_json(mut self, args: serde_json::Value) -> Self {
+        self.args = serde_json::to_vec(&args).unwrap();
+        self
+    }
+
+    /// Raw input, for methods that don't take JSON.
+    pub fn args(mut self, args: Vec<u8>) -> Self {
+        self.args = args;
+        self
+    }
+
+    pub fn gas(mut self, gas: Gas) -> Self {
+        self.gas = gas;
+        self
+    }
+
+    pub fn deposit(mut self, deposit: Balance) -> Self {
+        self.deposit = deposit;
+        self
+    }
+
+    pub fn attached_gas(&self) -> Gas {
+        self.gas
+    }
+
+    /// Send the call from another account instead of the client's default signer.
+    pub fn signer(mut self, signer: &Account) -> Self {
+        self.signer = signer.clone();
+        self
+    }
+
+    pub async fn transact(self) -> Result<CallOutcome, CallError> {
+        let label = format!("{}::{}", self.contract_id, self.method);
+        let res = self
+            .signer
+            .call(&self.contract_id, &self.method)
+            .args(self.args)
+            .gas(self.gas)
+            .deposit(self.deposit)
+            .transact()
+            .await
+            .map_err(|err| CallError::rpc(&label, err))?;
+
+        let outcome = check_res(res, &label)?;
+
+        let gas_key = match self.contract_name {
+            Some(contract_name) => format!("{}::{}", contract_name, self.method),
+            None => self.method.clone(),
+        };
+        record_gas(
+            &gas_key,
+            &self.contract_id,
+            self.signer.id(),
+            self.gas,
+            &outcome,
         );
+
+        Ok(outcome)
+    }
+}
+
+impl IntoFuture for ContractCall {
+    type Output = Result<CallOutcome, CallError>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.transact())
+    }
+}
+
+/// Check the result of a contract method call and print a short summary of it
+pub fn check_res(res: ExecutionFinalResult, msg: &str) -> Result<CallOutcome, CallError> {
+    match CallOutcome::from_final_result(msg, res) {
+        Ok(outcome) => {
+            outcome.logs.iter().for_each(|row| log!("{:?}", row));
+            log!(
+                "{} | OK ({} TGas)",
+                msg,
+                outcome.total_gas_burnt / 1_000_000_000_000
+            );
+
+            Ok(outcome)
+        }
+        Err(err) => {
+            log!("{} | FAIL", msg);
+            log!("{}", err);
+
+            Err(err)
+        }
     }
 }
 
 /// Checks that two amount are within epsilon
 pub fn assert_almost_eq(left: Balance, right: Balance, epsilon: Balance) {
-    println!("{} ~= {}", left, right);
+    log!("{} ~= {}", left, right);
 
     if left > right {
         assert!((left - right) < epsilon);
@@ -45,7 +146,7 @@ pub async fn create_account(
     account_name: &str,
     near_amount: u128,
 ) -> anyhow::Result<Account> {
-    println!("Creating account \"{}\"", account_name);
+    log!("Creating account \"{}\"", account_name);
 
     let owner = worker.root_account().unwrap();
 
@@ -65,7 +166,7 @@ pub async fn view_call(
     method: &str,
     args_json: serde_json::Value,
 ) -> anyhow::Result<ViewResultDetails> {
-    println!("view {}@{} {:?}", contract.id(), method, args_json);
+    log!("view {}@{} {:?}", contract.id(), method, args_json);
 
     let res = user
         .call(contract.id(), method.clone())
@@ -75,39 +176,3 @@ pub async fn view_call(
 
     Ok(res)
 }
-
-/// Fast-forward for a given number of epochs.
-pub async fn wait_epochs(worker: &Worker<Sandbox>, epochs_num: u64) -> anyhow::Result<()> {
-    println!("Fast-forwarding {} epochs...", epochs_num);
-
-    let mut i = 0;
-
-    while i < epochs_num {
-        wait_epoch(worker).await?;
-        i += 1;
-    }
-
-    Ok(())
-}
-
-/// Fast-forward for an epoch (approximate).
-pub async fn wait_epoch(worker: &Worker<Sandbox>) -> anyhow::Result<()> {
-    let start_block = worker.view_block().await?;
-    let blocks_per_iteration = 100;
-    let mut skipped_blocks = 0;
-
-    loop {
-        worker.fast_forward(blocks_per_iteration).await?;
-        skipped_blocks += blocks_per_iteration;
-
-        let block = worker.view_block().await?;
-
-        if block.epoch_id() != start_block.epoch_id() {
-            break;
-        }
-    }
-
-    println!("Fast-forwarded {} blocks.", skipped_blocks);
-
-    Ok(())
-}
@@ -1,83 +1,241 @@
-use near_sdk::json_types::U128;
+use near_sdk::{json_types::U128, serde::de::DeserializeOwned, Balance};
+use workspaces::{types::PublicKey, AccountId};
 
 use crate::*;
 
-pub async fn validator_get_account_unstaked_balance(
-    validator_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        validator_contract,
-        "get_account_unstaked_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
+/// Typed client for `contracts/staking_pool.wasm`.
+///
+/// Works the same way as [`StakingFarmClient`]: change methods return a [`ContractCall`]
+/// with default gas and deposit that can be overridden before awaiting. The private
+/// `on_stake_action` callback is not exposed.
+#[derive(Clone)]
+pub struct ValidatorPoolClient {
+    pub contract: Contract,
+    pub signer: Account,
 }
 
-pub async fn validator_get_account_staked_balance(
-    validator_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        validator_contract,
-        "get_account_staked_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
+impl ValidatorPoolClient {
+    pub fn new(contract: &Contract, signer: &Account) -> Self {
+        Self {
+            contract: contract.clone(),
+            signer: signer.clone(),
+        }
+    }
 
-pub async fn validator_get_account_total_balance(
-    validator_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<u128> {
-    let res: U128 = view_call(
-        user,
-        validator_contract,
-        "get_account_total_balance",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res.0)
-}
+    /// Same contract, different default signer.
+    pub fn with_signer(&self, signer: &Account) -> Self {
+        Self::new(&self.contract, signer)
+    }
 
-pub async fn validator_is_account_unstaked_balance_available(
-    validator_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<bool> {
-    let res: bool = view_call(
-        user,
-        validator_contract,
-        "is_account_unstaked_balance_available",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res)
-}
+    pub fn id(&self) -> &AccountId {
+        self.contract.id()
+    }
+
+    fn call(&self, method: &str) -> ContractCall {
+        ContractCall::new(&self.signer, self.contract.id(), method).contract_name("validator")
+    }
+
+    async fn view<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        args_json: serde_json::Value,
+    ) -> anyhow::Result<T> {
+        let res: T = view_call(&self.signer, &self.contract, method, args_json)
+            .await?
+            .json()?;
+
+        Ok(res)
+    }
+
+    // INIT METHODS ===========================
+    // ========================================
+
+    pub fn init(
+        &self,
+        owner_id: &AccountId,
+        stake_public_key: &PublicKey,
+        reward_fee_fraction: Ratio,
+    ) -> ContractCall {
+        self.call("new")
+            .args_json(json!({
+                "owner_id": owner_id,
+                "stake_public_key": stake_public_key,
+                "reward_fee_fraction": reward_fee_fraction,
+            }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    // STAKE METHODS ===========================
+    // ========================================
+
+    pub fn ping(&self) -> ContractCall {
+        self.call("ping").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn deposit(&self, amount: Balance) -> ContractCall {
+        self.call("deposit")
+            .deposit(amount)
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn deposit_and_stake(&self, amount: Balance) -> ContractCall {
+        self.call("deposit_and_stake")
+            .deposit(amount)
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn stake(&self, amount: Balance) -> ContractCall {
+        self.call("stake")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn stake_all(&self) -> ContractCall {
+        self.call("stake_all").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn unstake(&self, amount: Balance) -> ContractCall {
+        self.call("unstake")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn unstake_all(&self) -> ContractCall {
+        self.call("unstake_all").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn withdraw(&self, amount: Balance) -> ContractCall {
+        self.call("withdraw")
+            .args_json(json!({ "amount": U128(amount) }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn withdraw_all(&self) -> ContractCall {
+        self.call("withdraw_all").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub async fn get_account_unstaked_balance(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_unstaked_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn get_account_staked_balance(&self, account_id: &AccountId) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_staked_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn get_account_total_balance(&self, account_id: &AccountId) -> anyhow::Result<u128> {
+        let res: U128 = self
+            .view(
+                "get_account_total_balance",
+                json!({ "account_id": account_id }),
+            )
+            .await?;
+
+        Ok(res.0)
+    }
+
+    pub async fn is_account_unstaked_balance_available(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<bool> {
+        self.view(
+            "is_account_unstaked_balance_available",
+            json!({ "account_id": account_id }),
+        )
+        .await
+    }
+
+    pub async fn get_account(
+        &self,
+        account_id: &AccountId,
+    ) -> anyhow::Result<HumanReadableAccount> {
+        self.view("get_account", json!({ "account_id": account_id }))
+            .await
+    }
+
+    pub async fn get_accounts(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> anyhow::Result<Vec<HumanReadableAccount>> {
+        self.view(
+            "get_accounts",
+            json!({ "from_index": from_index, "limit": limit }),
+        )
+        .await
+    }
+
+    pub async fn get_number_of_accounts(&self) -> anyhow::Result<u64> {
+        self.view("get_number_of_accounts", json!({})).await
+    }
+
+    pub async fn get_total_staked_balance(&self) -> anyhow::Result<u128> {
+        let res: U128 = self.view("get_total_staked_balance", json!({})).await?;
+
+        Ok(res.0)
+    }
+
+    // OWNER METHODS ===========================
+    // ========================================
+
+    pub fn update_staking_key(&self, stake_public_key: &PublicKey) -> ContractCall {
+        self.call("update_staking_key")
+            .args_json(json!({ "stake_public_key": stake_public_key }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn update_reward_fee_fraction(&self, reward_fee_fraction: Ratio) -> ContractCall {
+        self.call("update_reward_fee_fraction")
+            .args_json(json!({ "reward_fee_fraction": reward_fee_fraction }))
+            .gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn vote(&self, voting_account_id: &AccountId, is_vote: bool) -> ContractCall {
+        self.call("vote")
+            .args_json(json!({
+                "voting_account_id": voting_account_id,
+                "is_vote": is_vote,
+            }))
+            .gas(parse_gas!("100 T") as u64)
+    }
+
+    pub fn pause_staking(&self) -> ContractCall {
+        self.call("pause_staking").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub fn resume_staking(&self) -> ContractCall {
+        self.call("resume_staking").gas(parse_gas!("50 T") as u64)
+    }
+
+    pub async fn get_owner_id(&self) -> anyhow::Result<AccountId> {
+        self.view("get_owner_id", json!({})).await
+    }
+
+    pub async fn get_reward_fee_fraction(&self) -> anyhow::Result<Ratio> {
+        self.view("get_reward_fee_fraction", json!({})).await
+    }
+
+    pub async fn get_staking_key(&self) -> anyhow::Result<PublicKey> {
+        self.view("get_staking_key", json!({})).await
+    }
 
-pub async fn validator_get_account(
-    validator_contract: &Contract,
-    user: &Account,
-) -> anyhow::Result<HumanReadableAccount> {
-    let res: HumanReadableAccount = view_call(
-        user,
-        validator_contract,
-        "get_account",
-        json!({"account_id": user.id()}),
-    )
-    .await?
-    .json()?;
-
-    Ok(res)
+    pub async fn is_staking_paused(&self) -> anyhow::Result<bool> {
+        self.view("is_staking_paused", json!({})).await
+    }
 }